@@ -0,0 +1,23 @@
+//! USB PD packet CRC-32.
+
+/// Reflected form of the CRC-32 polynomial 0x04C11DB7.
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// CRC-32 of `data` as carried at the end of a PD packet (little-endian).
+///
+/// Initial value and final XOR are 0xFFFFFFFF, with bits processed LSB
+/// first, as in the USB PD specification.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -3,10 +3,20 @@
 #[macro_use]
 pub(crate) mod fmt;
 
+mod crc;
+mod rx_queue;
+mod typec;
+
 mod token {
     pub const SOP1: u8 = 0x12;
     pub const SOP2: u8 = 0x13;
+    pub const SOP3: u8 = 0x1B;
+    pub const RESET1: u8 = 0x15;
+    pub const RESET2: u8 = 0x16;
     pub const PACK_SYM: u8 = 0x80;
+    // Fewest and most packed bytes a single PACK_SYM token can carry.
+    pub const PACK_SYM_MIN: usize = 2;
+    pub const PACK_SYM_MAX: usize = 30;
     pub const JAM_CRC: u8 = 0xFF;
     pub const EOP: u8 = 0x14;
     pub const TX_OFF: u8 = 0xFE;
@@ -15,21 +25,112 @@ mod token {
     // A mask to isolate the bits that identify a Start-of-Packet family token.
     pub const SOP_MASK: u8 = 0b1110_0000;
 
-    // The expected pattern after masking for any SOP*, SOP', or SOP'' token.
-    pub const SOP_PATTERN: u8 = 0b1110_0000;
+    // RX FIFO tokens after masking (datasheet Table 42).
+    pub const RX_SOP: u8 = 0b1110_0000;
+    pub const RX_SOP1: u8 = 0b1100_0000;
+    pub const RX_SOP2: u8 = 0b1010_0000;
+    pub const RX_SOP1DB: u8 = 0b1000_0000;
+    pub const RX_SOP2DB: u8 = 0b0110_0000;
+
+    /// Decode the SOP type of an RX FIFO token.
+    pub fn rx_sop_type(token: u8) -> Option<super::SopType> {
+        use super::SopType;
+        match token & SOP_MASK {
+            RX_SOP => Some(SopType::Sop),
+            RX_SOP1 => Some(SopType::SopPrime),
+            RX_SOP2 => Some(SopType::SopDoublePrime),
+            RX_SOP1DB => Some(SopType::SopPrimeDebug),
+            RX_SOP2DB => Some(SopType::SopDoublePrimeDebug),
+            _ => None,
+        }
+    }
 }
 
 use device_driver::{AsyncBufferInterface, AsyncRegisterInterface, BufferInterfaceError};
 use embedded_hal_async::i2c::I2c;
 use thiserror::Error;
 
-use crate::field_sets::{DeviceId, Mask, Maska, Maskb};
+use crate::field_sets::{DeviceId, Interrupta, Mask, Maska, Maskb};
 use embassy_time::{Duration, Instant, Timer};
 use usbpd_traits::{Driver as SinkDriver, DriverRxError, DriverTxError};
 
+use rx_queue::RxQueue;
+use typec::TypeC;
+pub use typec::{Attachment, DrpPreference, TypeCCurrent, TypeCState, VconnFault};
+
 device_driver::create_device!(device_name: FusbLowLevel, manifest: "device.yaml");
 pub const FUSB302B_I2C_ADDRESS: u8 = 0x22;
 
+/// Size of the TX FIFO in bytes.
+const TX_FIFO_SIZE: usize = 48;
+/// Size of the RX FIFO in bytes.
+const RX_FIFO_SIZE: usize = 80;
+/// Largest packet sent or received: header, extended header and 260 data bytes.
+pub const MAX_PACKET_LEN: usize = 264;
+/// TX token stream for the largest packet: ordered set, PACK_SYM runs, JAM_CRC, EOP and TXOFF.
+const MAX_TX_STREAM_LEN: usize =
+    4 + MAX_PACKET_LEN.div_ceil(token::PACK_SYM_MAX) + MAX_PACKET_LEN + 3;
+/// Time one packed byte takes on the wire (10 bits at 300 kbit/s).
+const BMC_BYTE_TIME: Duration = Duration::from_micros(34);
+/// Preamble sent before the first FIFO byte is consumed (64 bits at 300 kbit/s).
+const TX_PREAMBLE_TIME: Duration = Duration::from_micros(214);
+/// Message Type of a GoodCRC control message.
+const GOOD_CRC_MESSAGE_TYPE: u16 = 0x01;
+/// Message Type of an Accept control message.
+const ACCEPT_MESSAGE_TYPE: u16 = 0x03;
+/// Message Type of a Soft Reset control message.
+const SOFT_RESET_MESSAGE_TYPE: u16 = 0x0D;
+/// Port Data Role and Port Power Role bits of a message header.
+const HEADER_ROLE_BITS: u16 = 1 << 5 | 1 << 8;
+/// Specification Revision bits of a message header.
+const HEADER_SPEC_REV_BITS: u16 = 0b11 << 6;
+/// Longest gap in RX FIFO data before a packet that is still arriving is given up on.
+const RX_STALL_TIMEOUT: Duration = Duration::from_millis(1);
+/// nRetryCount: retries of a packet that is not acknowledged with GoodCRC.
+const N_RETRY_COUNT: u8 = 2;
+/// Time allowed for a reset the FUSB302B sends by itself, including its retries.
+const T_AUTO_RESET: Duration = Duration::from_millis(15);
+/// tBISTContMode: how long BIST Carrier Mode is transmitted (30–60 ms).
+const T_BIST_CONT_MODE: Duration = Duration::from_millis(45);
+
+/// Build the TX FIFO token stream for `data` sent with `sop` into `stream`.
+///
+/// The packet of at least 2 bytes is split into PACK_SYM runs of 2 to 30
+/// bytes. Returns the length of the stream, excluding the TXON command.
+fn build_tx_stream(sop: SopType, data: &[u8], stream: &mut [u8; MAX_TX_STREAM_LEN]) -> usize {
+    let mut pos = 0;
+    stream[pos..pos + 4].copy_from_slice(&sop.ordered_set());
+    pos += 4;
+    let mut rest = data;
+    while !rest.is_empty() {
+        // A tail too short for a run of its own borrows from the run before it
+        let len = match rest.len() {
+            n if n <= token::PACK_SYM_MAX => n,
+            n if n < token::PACK_SYM_MAX + token::PACK_SYM_MIN => n - token::PACK_SYM_MIN,
+            _ => token::PACK_SYM_MAX,
+        };
+        let (run, tail) = rest.split_at(len);
+        rest = tail;
+        stream[pos] = token::PACK_SYM | (run.len() as u8);
+        pos += 1;
+        stream[pos..pos + run.len()].copy_from_slice(run);
+        pos += run.len();
+    }
+    stream[pos..pos + 3].copy_from_slice(&[token::JAM_CRC, token::EOP, token::TX_OFF]);
+    pos += 3;
+    pos
+}
+
+/// Whether `packet` is a control message of `message_type`, going by its header.
+fn is_control_message(packet: &[u8], message_type: u16) -> bool {
+    let [h0, h1, ..] = *packet else {
+        return false;
+    };
+    let header = u16::from_le_bytes([h0, h1]);
+    // Control messages have no data objects and are not extended
+    header & 0x1F == message_type && header >> 12 == 0
+}
+
 /// Convert BcLvl enum to comparable u8 value
 fn bc_lvl_to_u8(lvl: BcLvl) -> u8 {
     match lvl {
@@ -47,6 +148,78 @@ pub enum FusbError<I2cErr> {
     I2c(I2cErr),
     #[error("Data length exceeds internal buffer size for I2C transaction")]
     LenExceedsBuffer,
+    #[error("No port partner attached")]
+    NotAttached,
+    #[error("Needs GoodCrcMode::Hardware")]
+    NoHardwareGoodCrc,
+    #[error("Value does not fit the register field")]
+    OutOfRange,
+}
+
+/// Error returned by [`Fusb302b::receive_sop`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RxError {
+    #[error("No packet received, or the packet was dropped")]
+    Discarded,
+    #[error("Hard Reset received, or the port partner detached")]
+    HardReset,
+    #[error("RX FIFO overflowed while the packet was being read")]
+    Overflow,
+    #[error("Packet failed the CRC check")]
+    BadCrc,
+    #[error("Soft Reset received; older packets were flushed")]
+    SoftReset,
+}
+
+/// Why [`Fusb302b::transmit_sop`] failed, see [`Fusb302b::last_tx_error`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxError {
+    #[error("Packet is shorter than a header or longer than MAX_PACKET_LEN")]
+    InvalidLength,
+    #[error("Reception of the SOP type is disabled, so no GoodCRC can be received")]
+    ReceptionDisabled,
+    #[error("I2C transfer to the FUSB302B failed")]
+    I2c,
+    #[error("Loading the TX FIFO failed")]
+    FifoLoad,
+    #[error("No GoodCRC received after {retries} hardware retries")]
+    NoGoodCrc { retries: u8 },
+    #[error("CC was busy and the packet was not sent")]
+    Collision,
+    #[error("Transmission did not complete in time")]
+    Timeout,
+    #[error("Hard Reset received or sent")]
+    HardReset,
+    #[error("The port partner detached")]
+    Detached,
+}
+
+impl From<TxError> for DriverTxError {
+    fn from(err: TxError) -> Self {
+        match err {
+            TxError::InvalidLength
+            | TxError::ReceptionDisabled
+            | TxError::I2c
+            | TxError::FifoLoad
+            | TxError::NoGoodCrc { .. }
+            | TxError::Collision
+            | TxError::Timeout => DriverTxError::Discarded,
+            TxError::HardReset | TxError::Detached => DriverTxError::HardReset,
+        }
+    }
+}
+
+impl From<RxError> for DriverRxError {
+    fn from(err: RxError) -> Self {
+        match err {
+            RxError::Discarded | RxError::Overflow | RxError::BadCrc | RxError::SoftReset => {
+                DriverRxError::Discarded
+            }
+            RxError::HardReset => DriverRxError::HardReset,
+        }
+    }
 }
 
 pub struct DeviceInterface<I2CBus> {
@@ -190,7 +363,22 @@ impl<P: embedded_hal_async::digital::Wait> InterruptPin for GpioInterrupt<P> {
     }
 }
 
-pub struct Fusb302b<I2CBus, E, V = (), I = ()>
+/// Trait for following the plug orientation, e.g. to switch an external USB 3
+/// SuperSpeed mux or SBU switch.
+///
+/// The default `()` implementation ignores orientation changes.
+pub trait OrientationSink {
+    /// Called with the active CC pin once a partner is attached, and with
+    /// `None` once it detaches.
+    fn set_orientation(&mut self, cc: Option<CcPin>) -> impl core::future::Future<Output = ()>;
+}
+
+/// No-op OrientationSink impl for `()`, used as default generic parameter.
+impl OrientationSink for () {
+    async fn set_orientation(&mut self, _cc: Option<CcPin>) {}
+}
+
+pub struct Fusb302b<I2CBus, E, V = (), I = (), O = ()>
 where
     I2CBus: I2c<Error = E>,
     E: core::fmt::Debug,
@@ -198,6 +386,20 @@ where
     pub ll: FusbLowLevel<DeviceInterface<I2CBus>>,
     vbus_source: VbusSource<V>,
     int_pin: I,
+    orientation: O,
+    typec: TypeC,
+    rx_queue: RxQueue,
+    crc_errors: u32,
+    good_crc_mode: GoodCrcMode,
+    auto_reset: AutoReset,
+    reset_event: Option<ResetEvent>,
+    soft_reset_received: bool,
+    soft_reset_handover: SoftResetHandover,
+    last_tx_error: Option<TxError>,
+    gcrc_sent_at: Option<Instant>,
+    /// Header role and revision bits matching `Switches1`, so a GoodCRC is
+    /// built without an I2C read.
+    header_bits: u16,
     _marker: core::marker::PhantomData<E>,
 }
 
@@ -209,6 +411,117 @@ pub enum CcPin {
     Cc2,
 }
 
+/// Start-of-packet type, selecting which port partner or cable plug a packet is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SopType {
+    /// SOP: the port partner.
+    Sop,
+    /// SOP': the cable plug closest to this port.
+    SopPrime,
+    /// SOP'': the far-end cable plug.
+    SopDoublePrime,
+    /// SOP'_Debug.
+    SopPrimeDebug,
+    /// SOP''_Debug.
+    SopDoublePrimeDebug,
+}
+
+impl SopType {
+    /// TX FIFO tokens for the K-code ordered set of this SOP type.
+    const fn ordered_set(self) -> [u8; 4] {
+        use token::{RESET2, SOP1, SOP2, SOP3};
+        match self {
+            SopType::Sop => [SOP1, SOP1, SOP1, SOP2],
+            SopType::SopPrime => [SOP1, SOP1, SOP3, SOP3],
+            SopType::SopDoublePrime => [SOP1, SOP3, SOP1, SOP3],
+            SopType::SopPrimeDebug => [SOP1, RESET2, RESET2, SOP3],
+            SopType::SopDoublePrimeDebug => [SOP1, RESET2, SOP3, SOP2],
+        }
+    }
+}
+
+/// Who acknowledges received packets with GoodCRC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GoodCrcMode {
+    /// The FUSB302B answers valid packets itself (`Switches1.auto_crc`).
+    Hardware,
+    /// The driver answers each valid packet from [`Fusb302b::receive_sop`],
+    /// later than the hardware would; see [`Fusb302b::transmit_good_crc`].
+    Software,
+    /// Nothing answers, e.g. for a passive listener, unless the protocol
+    /// layer calls [`Fusb302b::transmit_good_crc`] itself.
+    Disabled,
+}
+
+/// Recovery the FUSB302B runs by itself when a packet is not acknowledged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AutoReset {
+    /// Failed transmissions are only reported, the policy engine recovers.
+    Disabled,
+    /// Send Soft Reset once all retries of a packet failed (`Control3.auto_softreset`).
+    SoftReset,
+    /// As [`AutoReset::SoftReset`], then send Hard Reset if the Soft Reset is
+    /// not acknowledged either (`Control3.auto_hardreset`).
+    SoftThenHardReset,
+}
+
+/// A reset the FUSB302B sent by itself, see [`AutoReset`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetEvent {
+    /// Soft Reset was sent and acknowledged; MessageIDs start again from 0.
+    SoftReset,
+    /// Soft Reset was not acknowledged and no Hard Reset followed.
+    SoftResetFailed,
+    /// Hard Reset was sent.
+    HardReset,
+}
+
+/// How far an automatic Soft Reset has been handed to the policy engine.
+///
+/// [`SinkDriver`] has no error for it, so the policy engine is told as if the
+/// partner had sent the Soft Reset, and its Accept is not sent on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SoftResetHandover {
+    /// No automatic Soft Reset is pending.
+    Idle,
+    /// The Soft Reset was acknowledged; [`SinkDriver::receive`] reports it next.
+    Sent,
+    /// The Soft Reset was reported; the policy engine's Accept is dropped.
+    Reported,
+}
+
+/// Bytes of the packet being received that have been read from the RX FIFO so far.
+struct RxProgress {
+    /// When the packet was first seen in the RX FIFO.
+    started: Instant,
+    /// Bytes read, including the SOP token.
+    read: usize,
+    /// Bytes of the whole packet, including the SOP token and CRC, as far as
+    /// the headers read so far tell.
+    end: usize,
+    /// CC is quiet, so the rest of the packet is already in the FIFO.
+    complete: bool,
+}
+
+/// A packet received by [`Fusb302b::receive_sop`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReceivedPacket {
+    /// Start-of-packet type the packet was received with.
+    pub sop: SopType,
+    /// Length of the packet (header and data objects) in the buffer.
+    pub len: usize,
+    /// When the GoodCRC for the packet was sent, as seen from `I_GCRCSENT`
+    /// rising while [`Fusb302b::receive_sop`] was waiting. `None` for packets
+    /// that were not acknowledged, arrived before the call, or were
+    /// acknowledged before an older packet was read.
+    pub acked_at: Option<Instant>,
+}
+
 impl<I2CBus, E> Fusb302b<I2CBus, E, (), ()>
 where
     I2CBus: I2c<Error = E> + 'static,
@@ -216,12 +529,12 @@ where
 {
     /// Initialize with built-in VBUS detection and polling (no interrupt pin).
     pub async fn init(i2c: I2CBus) -> Result<Self, FusbError<E>> {
-        let mut driver = Self {
-            ll: FusbLowLevel::new(DeviceInterface::new(i2c)),
-            vbus_source: VbusSource::Internal,
-            int_pin: (),
-            _marker: core::marker::PhantomData,
-        };
+        let mut driver = Self::new(
+            FusbLowLevel::new(DeviceInterface::new(i2c)),
+            VbusSource::Internal,
+            (),
+            (),
+        );
 
         driver.init_hardware().await?;
         Ok(driver)
@@ -239,12 +552,12 @@ where
         i2c: I2CBus,
         int_pin: I,
     ) -> Result<Self, FusbError<E>> {
-        let mut driver = Self {
-            ll: FusbLowLevel::new(DeviceInterface::new(i2c)),
-            vbus_source: VbusSource::Internal,
+        let mut driver = Self::new(
+            FusbLowLevel::new(DeviceInterface::new(i2c)),
+            VbusSource::Internal,
             int_pin,
-            _marker: core::marker::PhantomData,
-        };
+            (),
+        );
 
         driver.init_hardware().await?;
         Ok(driver)
@@ -259,12 +572,12 @@ where
 {
     /// Initialize with a custom VBUS detection strategy and polling (no interrupt pin).
     pub async fn init_with_vbus_detect(i2c: I2CBus, vbus_detect: V) -> Result<Self, FusbError<E>> {
-        let mut driver = Self {
-            ll: FusbLowLevel::new(DeviceInterface::new(i2c)),
-            vbus_source: VbusSource::External(vbus_detect),
-            int_pin: (),
-            _marker: core::marker::PhantomData,
-        };
+        let mut driver = Self::new(
+            FusbLowLevel::new(DeviceInterface::new(i2c)),
+            VbusSource::External(vbus_detect),
+            (),
+            (),
+        );
 
         driver.init_hardware().await?;
         Ok(driver)
@@ -284,23 +597,78 @@ where
         vbus_detect: V,
         int_pin: I,
     ) -> Result<Self, FusbError<E>> {
-        let mut driver = Self {
-            ll: FusbLowLevel::new(DeviceInterface::new(i2c)),
-            vbus_source: VbusSource::External(vbus_detect),
+        let mut driver = Self::new(
+            FusbLowLevel::new(DeviceInterface::new(i2c)),
+            VbusSource::External(vbus_detect),
             int_pin,
-            _marker: core::marker::PhantomData,
-        };
+            (),
+        );
 
         driver.init_hardware().await?;
         Ok(driver)
     }
 }
 
-impl<I2CBus, E, V, I> Fusb302b<I2CBus, E, V, I>
+impl<I2CBus, E, V, I, O> Fusb302b<I2CBus, E, V, I, O>
 where
     I2CBus: I2c<Error = E> + 'static,
     E: core::fmt::Debug,
+    O: OrientationSink,
 {
+    /// Driver with freshly reset state around the given hardware handles.
+    fn new(
+        ll: FusbLowLevel<DeviceInterface<I2CBus>>,
+        vbus_source: VbusSource<V>,
+        int_pin: I,
+        orientation: O,
+    ) -> Self {
+        Self {
+            ll,
+            vbus_source,
+            int_pin,
+            orientation,
+            typec: TypeC::new(),
+            rx_queue: RxQueue::new(),
+            crc_errors: 0,
+            good_crc_mode: GoodCrcMode::Hardware,
+            auto_reset: AutoReset::Disabled,
+            reset_event: None,
+            soft_reset_received: false,
+            soft_reset_handover: SoftResetHandover::Idle,
+            last_tx_error: None,
+            gcrc_sent_at: None,
+            // Switches1 reset value: Revision 2.0, Sink, UFP
+            header_bits: 0b01 << 6,
+            _marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Replace the orientation sink, e.g. to drive a SuperSpeed mux from any
+    /// of the `init*` constructors.
+    pub fn with_orientation_sink<O2: OrientationSink>(
+        self,
+        orientation: O2,
+    ) -> Fusb302b<I2CBus, E, V, I, O2> {
+        Fusb302b {
+            ll: self.ll,
+            vbus_source: self.vbus_source,
+            int_pin: self.int_pin,
+            orientation,
+            typec: self.typec,
+            rx_queue: self.rx_queue,
+            crc_errors: self.crc_errors,
+            good_crc_mode: self.good_crc_mode,
+            auto_reset: self.auto_reset,
+            reset_event: self.reset_event,
+            soft_reset_received: self.soft_reset_received,
+            soft_reset_handover: self.soft_reset_handover,
+            last_tx_error: self.last_tx_error,
+            gcrc_sent_at: self.gcrc_sent_at,
+            header_bits: self.header_bits,
+            _marker: core::marker::PhantomData,
+        }
+    }
+
     /// Shared hardware initialization sequence.
     async fn init_hardware(&mut self) -> Result<(), FusbError<E>> {
         // Fully reset the FUSB302B
@@ -339,14 +707,16 @@ where
             .write_async(|r| r.set_int_mask(false))
             .await?;
 
-        // Configure hardware auto-retry: 2 retries per USB PD spec (nRetryCount = 2)
+        // Configure hardware auto-retry: 2 retries per USB PD spec (nRetryCount = 2).
+        // Without hardware GoodCRC it would also retry the driver's GoodCRC.
+        let auto_retry = self.good_crc_mode == GoodCrcMode::Hardware;
         self.ll
             .control_3()
             .write_async(|r| {
-                r.set_auto_retry(true);
-                r.set_n_retries(RetryCount::TwoRetries);
-                r.set_auto_softreset(false); // Let policy engine handle
-                r.set_auto_hardreset(false); // Let policy engine handle
+                r.set_auto_retry(auto_retry);
+                r.set_n_retries(RetryCount::TwoRetries); // N_RETRY_COUNT
+                r.set_auto_softreset(false); // Enabled by set_auto_reset()
+                r.set_auto_hardreset(false);
             })
             .await?;
 
@@ -356,8 +726,8 @@ where
             .write_async(|r| r.set_rx_flush(true))
             .await?;
 
-        // Detect and select CC line
-        self.detect_cc_pin().await?;
+        // Present Rd on both CC lines until a source attaches
+        self.enter_unattached_snk().await?;
 
         // Reset PD logic
         self.ll
@@ -368,225 +738,609 @@ where
         Ok(())
     }
 
-    /// Detect which CC pin is connected and configure switches accordingly
-    async fn detect_cc_pin(&mut self) -> Result<CcPin, FusbError<E>> {
-        // Measure CC1: PDWN1|PDWN2|MEAS_CC1
+    pub async fn get_device_info(&mut self) -> Result<DeviceId, FusbError<E>> {
+        self.ll.device_id().read_async().await
+    }
+
+    /// Bookkeeping for a Hard Reset sent or received.
+    ///
+    /// VBUS is about to be cycled, queued packets are stale and any role swap
+    /// or BIST Test Data mode is undone.
+    async fn handle_hard_reset(&mut self) {
+        self.typec.hard_reset();
+        self.rx_queue.clear();
+        self.soft_reset_received = false;
+        self.soft_reset_handover = SoftResetHandover::Idle;
+        self.gcrc_sent_at = None;
+        self.exit_bist_test_data().await.ok();
+        // On failure the swapped roles stay until the next attach
+        self.restore_default_roles().await.ok();
+    }
+
+    /// Read `Interrupta` and act on the flags that are not tied to the
+    /// caller's own operation.
+    ///
+    /// The register is read-cleared, so every loop polling it goes through
+    /// here: a VCONN fault is checked, a received Soft Reset is kept for
+    /// [`Fusb302b::receive_sop`] and a received Hard Reset is handled. The
+    /// caller still returns on `i_hardrst`.
+    async fn read_interrupta(&mut self) -> Result<Interrupta, FusbError<E>> {
+        let irqa = self.ll.interrupta().read_async().await?;
+        if irqa.i_ocp_temp() {
+            self.poll_vconn_fault().await?;
+        }
+        if irqa.i_softrst() {
+            self.soft_reset_received = true;
+        }
+        if irqa.i_hardrst() {
+            self.handle_hard_reset().await;
+        }
+        Ok(irqa)
+    }
+
+    /// Set the specification revision of GoodCRC messages (`Switches1.specrev`).
+    ///
+    /// Call this once the protocol layer has negotiated the revision with
+    /// the port partner, and again after a Hard Reset renegotiates it. The
+    /// revision is kept across attach and detach.
+    pub async fn set_spec_revision(&mut self, revision: SpecRev) -> Result<(), FusbError<E>> {
+        self.ll
+            .switches_1()
+            .modify_async(|r| r.set_specrev(revision))
+            .await?;
+        let bits: u16 = match revision {
+            SpecRev::Revision10 => 0b00,
+            SpecRev::Revision20 => 0b01,
+            SpecRev::Revision30 => 0b10,
+        };
+        self.header_bits = self.header_bits & !HEADER_SPEC_REV_BITS | bits << 6;
+
+        Ok(())
+    }
+
+    /// Set the port roles of SOP GoodCRC messages (`Switches1.powerrole`/`datarole`).
+    ///
+    /// Attach sets these from the Type-C role and a Hard Reset restores
+    /// them; call this after a PR_Swap or DR_Swap.
+    pub async fn set_roles(
+        &mut self,
+        power_role: PowerRolePort,
+        data_role: DataRolePort,
+    ) -> Result<(), FusbError<E>> {
         self.ll
-            .switches_0()
-            .write_async(|r| {
-                r.set_pdwn_1(true);
-                r.set_pdwn_2(true);
-                r.set_meas_cc_1(true);
-                r.set_meas_cc_2(false);
+            .switches_1()
+            .modify_async(|r| {
+                r.set_powerrole(power_role);
+                r.set_datarole(data_role);
             })
             .await?;
+        self.cache_roles(power_role, data_role);
 
-        Timer::after_millis(10).await;
+        Ok(())
+    }
 
-        let cc1 = bc_lvl_to_u8(self.ll.status_0().read_async().await?.bc_lvl());
+    /// Remember the port roles just written to `Switches1`, for GoodCRC headers.
+    fn cache_roles(&mut self, power_role: PowerRolePort, data_role: DataRolePort) {
+        let bits = u16::from(matches!(data_role, DataRolePort::Dfp)) << 5
+            | u16::from(matches!(power_role, PowerRolePort::Source)) << 8;
+        self.header_bits = self.header_bits & !HEADER_ROLE_BITS | bits;
+    }
 
-        // Measure CC2: PDWN1|PDWN2|MEAS_CC2
+    /// Let the FUSB302B escalate to Soft Reset and Hard Reset by itself; see [`AutoReset`].
+    ///
+    /// The escalation runs while [`Fusb302b::transmit_sop`] waits for its
+    /// packet to be acknowledged. The transmission then fails, and
+    /// [`Fusb302b::take_reset_event`] tells which reset was sent. Through
+    /// [`SinkDriver`] an acknowledged Soft Reset is reported by the next
+    /// `receive` instead, as if the partner had sent it.
+    pub async fn set_auto_reset(&mut self, mode: AutoReset) -> Result<(), FusbError<E>> {
+        self.auto_reset = mode;
         self.ll
-            .switches_0()
-            .write_async(|r| {
-                r.set_pdwn_1(true);
-                r.set_pdwn_2(true);
-                r.set_meas_cc_1(false);
-                r.set_meas_cc_2(true);
+            .control_3()
+            .modify_async(|r| {
+                r.set_auto_softreset(mode != AutoReset::Disabled);
+                r.set_auto_hardreset(mode == AutoReset::SoftThenHardReset);
             })
             .await?;
 
-        Timer::after_millis(10).await;
-
-        let cc2 = bc_lvl_to_u8(self.ll.status_0().read_async().await?.bc_lvl());
+        Ok(())
+    }
 
-        // Select the CC line with higher voltage (indicates connection)
-        let selected_cc = if cc1 > cc2 { CcPin::Cc1 } else { CcPin::Cc2 };
+    /// Take the reset the FUSB302B last sent by itself, if any.
+    pub fn take_reset_event(&mut self) -> Option<ResetEvent> {
+        self.reset_event.take()
+    }
 
-        // Configure switches for the selected CC line
-        match selected_cc {
-            CcPin::Cc1 => {
-                // TX on CC1, AUTO_CRC enabled
-                self.ll
-                    .switches_1()
-                    .write_async(|r| {
-                        r.set_txcc_1(true);
-                        r.set_txcc_2(false);
-                        r.set_auto_crc(true);
-                    })
-                    .await?;
-                // Measure CC1
-                self.ll
-                    .switches_0()
-                    .write_async(|r| {
-                        r.set_pdwn_1(true);
-                        r.set_pdwn_2(true);
-                        r.set_meas_cc_1(true);
-                        r.set_meas_cc_2(false);
-                    })
-                    .await?;
-            }
-            CcPin::Cc2 => {
-                // TX on CC2, AUTO_CRC enabled
-                self.ll
-                    .switches_1()
-                    .write_async(|r| {
-                        r.set_txcc_1(false);
-                        r.set_txcc_2(true);
-                        r.set_auto_crc(true);
-                    })
-                    .await?;
-                // Measure CC2
-                self.ll
-                    .switches_0()
-                    .write_async(|r| {
-                        r.set_pdwn_1(true);
-                        r.set_pdwn_2(true);
-                        r.set_meas_cc_1(false);
-                        r.set_meas_cc_2(true);
-                    })
-                    .await?;
-            }
+    /// Enter BIST Test Data mode (`Control3.bist_tmode`).
+    ///
+    /// Test frames are still acknowledged, but flushed from the RX FIFO
+    /// instead of being handed to [`Fusb302b::receive_sop`]. This needs
+    /// [`GoodCrcMode::Hardware`] and fails with
+    /// [`FusbError::NoHardwareGoodCrc`] otherwise, as the frames are flushed
+    /// before the driver could acknowledge them. The mode is left on a Hard
+    /// Reset or detach.
+    pub async fn enter_bist_test_data(&mut self) -> Result<(), FusbError<E>> {
+        if self.good_crc_mode != GoodCrcMode::Hardware {
+            return Err(FusbError::NoHardwareGoodCrc);
         }
+        self.ll
+            .control_3()
+            .modify_async(|r| r.set_bist_tmode(true))
+            .await?;
+        // Drop test frames that arrived before the mode took effect
+        self.ll
+            .control_1()
+            .modify_async(|r| r.set_rx_flush(true))
+            .await?;
+        self.rx_queue.clear();
 
-        Ok(selected_cc)
+        Ok(())
     }
 
-    pub async fn get_device_info(&mut self) -> Result<DeviceId, FusbError<E>> {
-        self.ll.device_id().read_async().await
+    /// Leave BIST Test Data mode.
+    pub async fn exit_bist_test_data(&mut self) -> Result<(), FusbError<E>> {
+        self.ll
+            .control_3()
+            .modify_async(|r| r.set_bist_tmode(false))
+            .await?;
+
+        Ok(())
     }
 }
 
-impl<I2CBus, E, V, I> SinkDriver for Fusb302b<I2CBus, E, V, I>
+impl<I2CBus, E, V, I, O> Fusb302b<I2CBus, E, V, I, O>
 where
     I2CBus: I2c<Error = E> + 'static,
     E: core::fmt::Debug,
     V: VbusDetect,
     I: InterruptPin,
+    O: OrientationSink,
 {
-    const HAS_AUTO_GOOD_CRC: bool = true;
-    const HAS_AUTO_RETRY: bool = true;
+    /// Transmit a Cable Reset ordered set (RST-1, Sync-1, RST-1, Sync-3) through the TX FIFO.
+    ///
+    /// This resets the cable plugs (SOP' and SOP'') without affecting the port
+    /// partner. Hardware auto-retry is suspended meanwhile, as no GoodCRC
+    /// follows an ordered set.
+    pub async fn transmit_cable_reset(&mut self) -> Result<(), DriverTxError> {
+        self.transmit_unacknowledged(&[
+            token::RESET1,
+            token::SOP1,
+            token::RESET1,
+            token::SOP3,
+            token::TX_OFF,
+        ])
+        .await
+    }
 
-    async fn wait_for_vbus(&mut self) {
-        match &mut self.vbus_source {
-            VbusSource::Internal => {
-                // Poll STATUS0.VBUSOK (R-only, bit 7) until VBUS is above ~4.0V.
-                loop {
-                    if let Ok(status0) = self.ll.status_0().read_async().await
-                        && status0.vbusok()
-                    {
-                        return;
-                    }
-                    self.int_pin.wait_for_interrupt().await;
-                }
-            }
-            VbusSource::External(v) => v.wait_for_vbus().await,
+    /// Transmit BIST Carrier Mode, a continuous BMC carrier, for tBISTContMode.
+    ///
+    /// Answers a BIST Carrier Mode request from a tester (`Control1.bist_mode2`).
+    /// The carrier stops by itself, after which the TX FIFO is flushed and
+    /// the oscillator is set back to how it was.
+    pub async fn transmit_bist_carrier(&mut self) -> Result<(), DriverTxError> {
+        let power = self
+            .ll
+            .power()
+            .read_async()
+            .await
+            .map_err(|_| DriverTxError::Discarded)?;
+        self.ll
+            .power()
+            .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(true))
+            .await
+            .map_err(|_| DriverTxError::Discarded)?;
+
+        let tx_result = async {
+            self.ll
+                .control_1()
+                .modify_async(|r| r.set_bist_mode_2(true))
+                .await?;
+            self.ll
+                .control_0()
+                .modify_async(|r| r.set_tx_start(true))
+                .await?;
+            Timer::after(T_BIST_CONT_MODE).await;
+            Ok::<_, FusbError<E>>(())
         }
+        .await
+        .map_err(|_| DriverTxError::Discarded);
+
+        self.ll
+            .control_1()
+            .modify_async(|r| r.set_bist_mode_2(false))
+            .await
+            .ok();
+        self.ll
+            .control_0()
+            .modify_async(|r| r.set_tx_flush(true))
+            .await
+            .ok();
+        self.ll.power().write_async(|r| *r = power).await.ok();
+        tx_result
     }
 
-    async fn transmit_hard_reset(&mut self) -> Result<(), DriverTxError> {
+    /// Build a control message header from the revision and port roles in `Switches1`.
+    ///
+    /// With `from_partner`, the header is the one the port partner would
+    /// send, so both roles are flipped.
+    fn control_header(
+        &self,
+        sop: SopType,
+        message_type: u16,
+        message_id: u8,
+        from_partner: bool,
+    ) -> u16 {
+        // Port roles only apply to SOP; toward a cable plug both bits are zero
+        let bits = match sop {
+            SopType::Sop if from_partner => self.header_bits ^ HEADER_ROLE_BITS,
+            SopType::Sop => self.header_bits,
+            _ => self.header_bits & HEADER_SPEC_REV_BITS,
+        };
+
+        message_type | bits | u16::from(message_id & 0x7) << 9
+    }
+
+    /// Transmit a GoodCRC for the packet with `message_id` received as `sop`.
+    ///
+    /// Used by [`GoodCrcMode::Software`], or by a protocol layer acknowledging
+    /// packets itself with [`GoodCrcMode::Disabled`]. The port roles and
+    /// specification revision are the ones last written to `Switches1`, and
+    /// the GoodCRC goes out with a single FIFO write; the oscillator and
+    /// auto-retry are left as they are.
+    ///
+    /// tTransmit (195 µs) is not met, as the packet has to be read over I2C
+    /// first. Most partners still accept a GoodCRC that arrives within their
+    /// tReceive (0.9 ms), which a 1 MHz bus makes for short packets and a
+    /// 400 kHz bus only for control messages. A later GoodCRC makes the
+    /// partner retry the packet, and finally send Soft Reset.
+    pub async fn transmit_good_crc(
+        &mut self,
+        sop: SopType,
+        message_id: u8,
+    ) -> Result<(), DriverTxError> {
+        let header = self
+            .control_header(sop, GOOD_CRC_MESSAGE_TYPE, message_id, false)
+            .to_le_bytes();
+        let [k1, k2, k3, k4] = sop.ordered_set();
+
+        self.ll
+            .fifo()
+            .write_all_async(&[
+                k1,
+                k2,
+                k3,
+                k4,
+                token::PACK_SYM | 2,
+                header[0],
+                header[1],
+                token::JAM_CRC,
+                token::EOP,
+                token::TX_OFF,
+                token::TX_ON,
+            ])
+            .await
+            .map_err(|_| DriverTxError::Discarded)
+    }
+
+    /// Transmit a TX token stream that is not answered by a GoodCRC.
+    ///
+    /// Hardware auto-retry is suspended meanwhile, and completion is taken
+    /// from `Status1.tx_empty` instead of `I_TXSENT`.
+    async fn transmit_unacknowledged(&mut self, stream: &[u8]) -> Result<(), DriverTxError> {
         self.ll
             .control_3()
-            .modify_async(|r| r.set_send_hard_reset(true))
+            .modify_async(|r| r.set_auto_retry(false))
+            .await
+            .map_err(|_| DriverTxError::Discarded)?;
+        self.ll
+            .power()
+            .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(true))
             .await
             .map_err(|_| DriverTxError::Discarded)?;
 
-        let deadline = Instant::now() + Duration::from_millis(5);
-        loop {
-            let irqa = self
-                .ll
-                .interrupta()
-                .read_async()
+        let tx_result = async {
+            self.write_tx_stream(stream)
                 .await
                 .map_err(|_| DriverTxError::Discarded)?;
-            if irqa.i_hardsent() {
-                self.ll
-                    .interrupta()
-                    .modify_async(|r| r.set_i_hardsent(true))
+
+            let deadline = Instant::now() + Duration::from_millis(5);
+            loop {
+                let status1 = self
+                    .ll
+                    .status_1()
+                    .read_async()
                     .await
-                    .ok();
-                return Ok(());
+                    .map_err(|_| DriverTxError::Discarded)?;
+                if status1.tx_empty() {
+                    return Ok(());
+                }
+                if Instant::now() >= deadline {
+                    return Err(DriverTxError::Discarded);
+                }
+                Timer::after_micros(100).await;
             }
-            if Instant::now() >= deadline {
-                return Err(DriverTxError::Discarded);
+        }
+        .await;
+
+        self.ll
+            .power()
+            .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(false))
+            .await
+            .ok();
+        let auto_retry = self.good_crc_mode == GoodCrcMode::Hardware;
+        self.ll
+            .control_3()
+            .modify_async(|r| r.set_auto_retry(auto_retry))
+            .await
+            .ok();
+        tx_result
+    }
+
+    /// Load a TX token stream into the FIFO and start the transmitter.
+    ///
+    /// A stream that fits the 48-byte FIFO is written in one go, followed by
+    /// TXON. A longer one is started as soon as the FIFO is full, and the rest
+    /// is topped up as the transmitter drains it; the free space is estimated
+    /// from the time since TXON and double-checked with `Status1.tx_full`.
+    async fn write_tx_stream(&mut self, stream: &[u8]) -> Result<(), FusbError<E>> {
+        let first = stream.len().min(TX_FIFO_SIZE);
+        self.ll.fifo().write_all_async(&stream[..first]).await?;
+        self.ll.fifo().write_all_async(&[token::TX_ON]).await?;
+
+        let started = Instant::now() + TX_PREAMBLE_TIME;
+        let mut written = first;
+        while written < stream.len() {
+            let drained = Instant::now()
+                .checked_duration_since(started)
+                .map_or(0, |t| (t.as_ticks() / BMC_BYTE_TIME.as_ticks()) as usize);
+            // Never more than the FIFO holds, which also keeps each write
+            // within the 63 bytes of the I2C buffer after a late wake-up
+            let free = (TX_FIFO_SIZE + drained)
+                .saturating_sub(written)
+                .min(TX_FIFO_SIZE);
+            if free == 0 || self.ll.status_1().read_async().await?.tx_full() {
+                Timer::after(BMC_BYTE_TIME * 4).await;
+                continue;
             }
-            self.int_pin.wait_for_interrupt().await;
+
+            let end = stream.len().min(written + free);
+            self.ll
+                .fifo()
+                .write_all_async(&stream[written..end])
+                .await?;
+            written = end;
         }
+
+        Ok(())
     }
 
-    async fn transmit(&mut self, data: &[u8]) -> Result<(), DriverTxError> {
+    /// Transmit a packet starting with the ordered set of `sop`.
+    ///
+    /// [`SinkDriver::transmit`] is this with [`SopType::Sop`]; the other types
+    /// address cable plugs and debug targets, and need their reception
+    /// enabled with [`Self::enable_sop_reception`] first. Packets of up to
+    /// [`MAX_PACKET_LEN`] bytes are accepted, so unchunked extended
+    /// messages can be sent. Why a transmission failed is kept for
+    /// [`Fusb302b::last_tx_error`].
+    pub async fn transmit_sop(&mut self, sop: SopType, data: &[u8]) -> Result<(), DriverTxError> {
+        let result = self.try_transmit_sop(sop, data).await;
+        self.last_tx_error = result.err();
+        result.map_err(DriverTxError::from)
+    }
+
+    /// Why the last [`Fusb302b::transmit_sop`] failed, or `None` if it succeeded.
+    ///
+    /// The FUSB302B does not count retries that end in a GoodCRC, so the
+    /// retry count is only known once all of them failed.
+    pub fn last_tx_error(&self) -> Option<TxError> {
+        self.last_tx_error
+    }
+
+    /// [`Fusb302b::transmit_sop`] with the detailed error.
+    async fn try_transmit_sop(&mut self, sop: SopType, data: &[u8]) -> Result<(), TxError> {
+        if data.len() < 2 || data.len() > MAX_PACKET_LEN {
+            return Err(TxError::InvalidLength);
+        }
+        // The GoodCRC of a cable plug or debug target, like its reply, is
+        // only received with its SOP type enabled
+        if sop != SopType::Sop {
+            let control1 = self
+                .ll
+                .control_1()
+                .read_async()
+                .await
+                .map_err(|_| TxError::I2c)?;
+            let enabled = match sop {
+                SopType::Sop => true,
+                SopType::SopPrime => control1.ensop_1(),
+                SopType::SopDoublePrime => control1.ensop_2(),
+                SopType::SopPrimeDebug => control1.ensop_1_db(),
+                SopType::SopDoublePrimeDebug => control1.ensop_2_db(),
+            };
+            if !enabled {
+                return Err(TxError::ReceptionDisabled);
+            }
+        }
+        if self.poll_detach().await.map_err(|_| TxError::I2c)? {
+            return Err(TxError::Detached);
+        }
+
         self.ll
             .power()
             .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(true))
             .await
-            .map_err(|_| DriverTxError::Discarded)?;
+            .map_err(|_| TxError::I2c)?;
 
-        let fifo_result = async {
-            let mut fifo_buffer = [0u8; 40];
-            let mut pos = 0;
-            let packet_byte_count = data.len();
-            fifo_buffer[pos..pos + 5].copy_from_slice(&[
-                token::SOP1,
-                token::SOP1,
-                token::SOP1,
-                token::SOP2,
-                token::PACK_SYM | (packet_byte_count as u8),
-            ]);
-            pos += 5;
-            fifo_buffer[pos..pos + data.len()].copy_from_slice(data);
-            pos += data.len();
-            fifo_buffer[pos..pos + 4].copy_from_slice(&[
-                token::JAM_CRC,
-                token::EOP,
-                token::TX_OFF,
-                token::TX_ON,
-            ]);
-            pos += 4;
-            self.ll.fifo().write_all_async(&fifo_buffer[..pos]).await
-        }
-        .await;
+        let mut stream = [0u8; MAX_TX_STREAM_LEN];
+        let stream_len = build_tx_stream(sop, data, &mut stream);
+        let tx_result = if stream_len > TX_FIFO_SIZE || self.good_crc_mode != GoodCrcMode::Hardware
+        {
+            self.transmit_with_driver_retries(&stream[..stream_len])
+                .await
+        } else {
+            self.transmit_stream(&stream[..stream_len]).await
+        };
+
+        self.ll
+            .power()
+            .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(false))
+            .await
+            .ok();
+        tx_result
+    }
 
-        if fifo_result.is_err() {
+    /// Transmit a stream, retrying in the driver instead of the FUSB302B.
+    ///
+    /// A hardware retry resends what is left in the TX FIFO, which for a
+    /// streamed packet longer than the FIFO is only its tail. Without
+    /// [`GoodCrcMode::Hardware`] auto-retry is kept off altogether, as it
+    /// would also resend the driver's GoodCRC. Hardware retries and the
+    /// automatic Soft Reset are suspended meanwhile, and the whole stream is
+    /// written again for each retry.
+    async fn transmit_with_driver_retries(&mut self, stream: &[u8]) -> Result<(), TxError> {
+        self.ll
+            .control_3()
+            .modify_async(|r| {
+                // Only for I_RETRYFAIL; the chip sends nothing after a GoodCRC
+                r.set_auto_retry(true);
+                r.set_n_retries(RetryCount::NoRetries);
+                r.set_auto_softreset(false);
+                r.set_auto_hardreset(false);
+            })
+            .await
+            .map_err(|_| TxError::I2c)?;
+        let auto_reset = core::mem::replace(&mut self.auto_reset, AutoReset::Disabled);
+
+        let mut tx_result = Err(TxError::Timeout);
+        for _ in 0..=N_RETRY_COUNT {
+            tx_result = self.transmit_stream(stream).await;
+            if !matches!(tx_result, Err(TxError::NoGoodCrc { .. })) {
+                break;
+            }
             self.ll
-                .power()
-                .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(false))
+                .control_0()
+                .modify_async(|r| r.set_tx_flush(true))
                 .await
                 .ok();
-            return Err(DriverTxError::Discarded);
         }
 
-        // With hardware auto-retry (up to 3 attempts), we need more time than the original 5ms
-        let deadline = Instant::now() + Duration::from_millis(15);
-        let mut tx_result = Err(DriverTxError::Discarded);
+        self.auto_reset = auto_reset;
+        let auto_retry = self.good_crc_mode == GoodCrcMode::Hardware;
+        self.ll
+            .control_3()
+            .modify_async(|r| {
+                r.set_auto_retry(auto_retry);
+                r.set_n_retries(RetryCount::TwoRetries);
+                r.set_auto_softreset(auto_reset != AutoReset::Disabled);
+                r.set_auto_hardreset(auto_reset == AutoReset::SoftThenHardReset);
+            })
+            .await
+            .map_err(|_| TxError::I2c)?;
+        match tx_result {
+            Err(TxError::NoGoodCrc { .. }) => Err(TxError::NoGoodCrc {
+                retries: N_RETRY_COUNT,
+            }),
+            result => result,
+        }
+    }
+
+    /// Load `stream` into the TX FIFO and wait for the outcome of its transmission.
+    async fn transmit_stream(&mut self, stream: &[u8]) -> Result<(), TxError> {
+        let stream_len = stream.len();
+        // Clear flags left over from earlier transmissions, such as the
+        // I_RETRYFAIL a software GoodCRC may latch
+        if self
+            .read_interrupta()
+            .await
+            .map_err(|_| TxError::I2c)?
+            .i_hardrst()
+        {
+            return Err(TxError::HardReset);
+        }
+        if self.write_tx_stream(stream).await.is_err() {
+            return Err(TxError::FifoLoad);
+        }
+
+        // With hardware auto-retry (up to 3 attempts), we need more time than the original 5ms,
+        // plus the air time of long extended messages
+        let mut deadline =
+            Instant::now() + Duration::from_millis(15) + BMC_BYTE_TIME * (3 * stream_len as u32);
+        let mut tx_result = Err(TxError::Timeout);
+        let mut soft_reset_sent = false;
 
         loop {
-            let irqa = self
-                .ll
-                .interrupta()
-                .read_async()
-                .await
-                .map_err(|_| DriverTxError::Discarded)?;
+            let irqa = self.read_interrupta().await.map_err(|_| TxError::I2c)?;
+            // Checked before I_TXSENT: both are set when the automatic Soft
+            // Reset is acknowledged before this read
+            if irqa.i_retryfail() && !soft_reset_sent {
+                // Hardware auto-retry exhausted all attempts without receiving GoodCRC
+                self.ll
+                    .interrupta()
+                    .modify_async(|r| r.set_i_retryfail(true))
+                    .await
+                    .ok();
+                let control3 = self
+                    .ll
+                    .control_3()
+                    .read_async()
+                    .await
+                    .map_err(|_| TxError::I2c)?;
+                let retries = match control3.n_retries() {
+                    RetryCount::NoRetries => 0,
+                    RetryCount::OneRetry => 1,
+                    RetryCount::TwoRetries => 2,
+                    RetryCount::ThreeRetries => 3,
+                };
+                tx_result = Err(TxError::NoGoodCrc { retries });
+                if self.auto_reset == AutoReset::Disabled {
+                    break;
+                }
+                // The FUSB302B follows up with a Soft Reset by itself
+                soft_reset_sent = true;
+                deadline = Instant::now() + T_AUTO_RESET;
+            }
             if irqa.i_txsent() {
                 self.ll
                     .interrupta()
                     .modify_async(|r| r.set_i_txsent(true))
                     .await
                     .ok();
-                tx_result = Ok(());
+                if soft_reset_sent {
+                    // The partner acknowledged the Soft Reset; the protocol
+                    // layer resets its MessageIDCounter on the event. RX is
+                    // left alone, as the partner's Accept follows right away.
+                    self.reset_event = Some(ResetEvent::SoftReset);
+                } else {
+                    tx_result = Ok(());
+                }
                 break;
             }
-            if irqa.i_retryfail() {
-                // Hardware auto-retry exhausted all attempts without receiving GoodCRC
+            if irqa.i_softfail() {
                 self.ll
                     .interrupta()
-                    .modify_async(|r| r.set_i_retryfail(true))
+                    .modify_async(|r| r.set_i_softfail(true))
                     .await
                     .ok();
-                tx_result = Err(DriverTxError::Discarded);
+                if self.auto_reset != AutoReset::SoftThenHardReset {
+                    self.reset_event = Some(ResetEvent::SoftResetFailed);
+                    break;
+                }
+                // ...and then with a Hard Reset
+                deadline = Instant::now() + T_AUTO_RESET;
+            }
+            if irqa.i_hardsent() {
+                self.ll
+                    .interrupta()
+                    .modify_async(|r| r.set_i_hardsent(true))
+                    .await
+                    .ok();
+                self.handle_hard_reset().await;
+                self.reset_event = Some(ResetEvent::HardReset);
+                tx_result = Err(TxError::HardReset);
                 break;
             }
             if irqa.i_hardrst() {
-                tx_result = Err(DriverTxError::HardReset);
+                tx_result = Err(TxError::HardReset);
                 break;
             }
             if self
@@ -594,7 +1348,7 @@ where
                 .interrupt()
                 .read_async()
                 .await
-                .map_err(|_| DriverTxError::Discarded)?
+                .map_err(|_| TxError::I2c)?
                 .i_collision()
             {
                 self.ll
@@ -602,7 +1356,7 @@ where
                     .modify_async(|r| r.set_i_collision(true))
                     .await
                     .ok();
-                tx_result = Err(DriverTxError::Discarded);
+                tx_result = Err(TxError::Collision);
                 break;
             }
             if Instant::now() >= deadline {
@@ -611,25 +1365,179 @@ where
             self.int_pin.wait_for_interrupt().await;
         }
 
-        self.ll
-            .power()
-            .modify_async(|r| r.set_pwr_3_internal_oscillator_enable(false))
-            .await
-            .ok();
         tx_result
     }
 
-    async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, DriverRxError> {
+    /// Select who acknowledges received packets; see [`GoodCrcMode`].
+    ///
+    /// `Switches1.auto_crc` is only set in [`GoodCrcMode::Hardware`], and is
+    /// kept that way across attach and detach. Hardware auto-retry
+    /// (`Control3.auto_retry`) follows it, as a retried GoodCRC is a second
+    /// message to the partner; transmissions are then retried by the driver.
+    pub async fn set_good_crc_mode(&mut self, mode: GoodCrcMode) -> Result<(), FusbError<E>> {
+        self.good_crc_mode = mode;
+        self.ll
+            .switches_1()
+            .modify_async(|r| r.set_auto_crc(mode == GoodCrcMode::Hardware))
+            .await?;
+        self.ll
+            .control_3()
+            .modify_async(|r| r.set_auto_retry(mode == GoodCrcMode::Hardware))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Current GoodCRC mode.
+    pub fn good_crc_mode(&self) -> GoodCrcMode {
+        self.good_crc_mode
+    }
+
+    /// Enable or disable reception of `sop` packets (`Control1.ensop*`).
+    ///
+    /// SOP packets are always received. Other types have to be enabled to
+    /// transmit them as well, as their GoodCRC would be missed otherwise.
+    pub async fn enable_sop_reception(
+        &mut self,
+        sop: SopType,
+        enabled: bool,
+    ) -> Result<(), FusbError<E>> {
+        self.ll
+            .control_1()
+            .modify_async(|r| match sop {
+                SopType::Sop => {}
+                SopType::SopPrime => r.set_ensop_1(enabled),
+                SopType::SopDoublePrime => r.set_ensop_2(enabled),
+                SopType::SopPrimeDebug => r.set_ensop_1_db(enabled),
+                SopType::SopDoublePrimeDebug => r.set_ensop_2_db(enabled),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Read `buf.len()` bytes of a packet that may still be arriving.
+    ///
+    /// Once CC is quiet (`Status0.activity` low) the packet is complete and
+    /// is read in one burst. Until then bytes arrive at one per
+    /// [`BMC_BYTE_TIME`], so everything up to that estimate is read at once;
+    /// when the estimate runs out `Status1.rx_empty` tells whether at least
+    /// one more byte is there. A full FIFO only loses bytes of this packet
+    /// if more of them are still to come than it holds.
+    async fn read_rx_fifo(&mut self, buf: &mut [u8], rx: &mut RxProgress) -> Result<(), RxError> {
+        let mut pos = 0;
+        let mut last_progress = Instant::now();
+        while pos < buf.len() {
+            let mut count = buf.len() - pos;
+            if !rx.complete {
+                let status1 = self
+                    .ll
+                    .status_1()
+                    .read_async()
+                    .await
+                    .map_err(|_| RxError::Discarded)?;
+                // Only a packet with more to come than the FIFO holds can overflow
+                if rx.end - rx.read > RX_FIFO_SIZE {
+                    // I_ALERT latches an RX_FULL that came and went since the last check
+                    let alert = self
+                        .ll
+                        .interrupt()
+                        .read_async()
+                        .await
+                        .map_err(|_| RxError::Discarded)?
+                        .i_alert();
+                    if status1.rx_full() || alert {
+                        return Err(RxError::Overflow);
+                    }
+                }
+
+                if !status1.rx_full() {
+                    let elapsed = Instant::now() - rx.started;
+                    let arrived = 1 + (elapsed.as_ticks() / BMC_BYTE_TIME.as_ticks()) as usize;
+                    count = arrived.saturating_sub(rx.read).min(count);
+                }
+                if count == 0 {
+                    let status0 = self
+                        .ll
+                        .status_0()
+                        .read_async()
+                        .await
+                        .map_err(|_| RxError::Discarded)?;
+                    if !status0.activity() {
+                        rx.complete = true;
+                        continue;
+                    }
+                    if !status1.rx_empty() {
+                        count = 1;
+                    } else if Instant::now() - last_progress >= RX_STALL_TIMEOUT {
+                        return Err(RxError::Discarded);
+                    } else {
+                        Timer::after(BMC_BYTE_TIME).await;
+                        continue;
+                    }
+                }
+            }
+
+            self.ll
+                .fifo()
+                .read_exact_async(&mut buf[pos..pos + count])
+                .await
+                .map_err(|_| RxError::Discarded)?;
+            pos += count;
+            rx.read += count;
+            last_progress = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Receive a packet of any enabled SOP type.
+    ///
+    /// Only SOP packets are received unless other types are enabled with
+    /// [`Self::enable_sop_reception`]. The packet is written to `buffer`
+    /// without its CRC. Long (unchunked extended) packets are read while they
+    /// are still arriving; an RX FIFO overflow drops the packet with
+    /// [`RxError::Overflow`]. Every complete packet in the FIFO is moved to an
+    /// internal queue, so back-to-back packets are served by later calls.
+    /// A received Soft Reset flushes the FIFO and the queue and is reported
+    /// as [`RxError::SoftReset`].
+    pub async fn receive_sop(&mut self, buffer: &mut [u8]) -> Result<ReceivedPacket, RxError> {
         let deadline = Instant::now() + Duration::from_millis(20);
+        let mut waited = false;
+        self.gcrc_sent_at = None;
         loop {
             let irqa = self
+                .read_interrupta()
+                .await
+                .map_err(|_| RxError::Discarded)?;
+            if irqa.i_hardrst() {
+                return Err(RxError::HardReset);
+            }
+            if self.soft_reset_received {
+                self.soft_reset_received = false;
+                self.flush_after_soft_reset().await?;
+                return Err(RxError::SoftReset);
+            }
+            // A detach is reported as a Hard Reset so the policy engine
+            // restarts from wait_for_vbus() on the next attach
+            if self.poll_detach().await.map_err(|_| RxError::Discarded)? {
+                return Err(RxError::HardReset);
+            }
+            if !self.rx_queue.is_empty() {
+                break;
+            }
+            // Timestamp the GoodCRC as soon as INT_N wakes us, not when the
+            // packet is returned. I_GCRCSENT is latched, so a flag already set
+            // before the first wait is of unknown age and only cleared.
+            let gcrc_sent = self
                 .ll
-                .interrupta()
+                .interruptb()
                 .read_async()
                 .await
-                .map_err(|_| DriverRxError::Discarded)?;
-            if irqa.i_hardrst() {
-                return Err(DriverRxError::HardReset);
+                .map_err(|_| RxError::Discarded)?
+                .i_gcrcsent();
+            if gcrc_sent && waited {
+                self.gcrc_sent_at = Some(Instant::now());
             }
             // Use RX_EMPTY from STATUS1 (R-only) instead of I_CRC_CHK from
             // INTERRUPT (R/C). The INTERRUPT register is read-cleared, and
@@ -640,70 +1548,351 @@ where
                 .status_1()
                 .read_async()
                 .await
-                .map_err(|_| DriverRxError::Discarded)?;
+                .map_err(|_| RxError::Discarded)?;
             if !status1.rx_empty() {
                 break;
             }
             if Instant::now() >= deadline {
-                return Err(DriverRxError::Discarded);
+                return Err(RxError::Discarded);
             }
             self.int_pin.wait_for_interrupt().await;
+            waited = true;
+        }
+
+        // Queue everything that is already in the FIFO, then serve the oldest packet
+        self.drain_rx_fifo().await?;
+        self.rx_queue.pop(buffer).unwrap_or(Err(RxError::Discarded))
+    }
+
+    /// Flush the packets a received Soft Reset makes stale, the Soft Reset included.
+    ///
+    /// In [`GoodCrcMode::Software`] the Soft Reset is acknowledged first; it
+    /// always carries MessageID 0.
+    async fn flush_after_soft_reset(&mut self) -> Result<(), RxError> {
+        if self.good_crc_mode == GoodCrcMode::Software {
+            self.transmit_good_crc(SopType::Sop, 0).await.ok();
+        }
+        self.ll
+            .control_1()
+            .modify_async(|r| r.set_rx_flush(true))
+            .await
+            .map_err(|_| RxError::Discarded)?;
+        self.rx_queue.clear();
+        self.gcrc_sent_at = None;
+
+        Ok(())
+    }
+
+    /// Move every packet waiting in the RX FIFO into the receive queue.
+    ///
+    /// Stops once the FIFO is empty or the queue is full. An error is only
+    /// returned if nothing could be queued.
+    async fn drain_rx_fifo(&mut self) -> Result<(), RxError> {
+        let mut queued = false;
+        while !self.rx_queue.is_full() {
+            let status1 = self
+                .ll
+                .status_1()
+                .read_async()
+                .await
+                .map_err(|_| RxError::Discarded)?;
+            if status1.rx_empty() {
+                // I_GCRCSENT only keeps the latest GoodCRC, which is for the
+                // newest packet
+                if queued && let Some(at) = self.gcrc_sent_at.take() {
+                    self.rx_queue.set_newest_acked_at(at);
+                }
+                break;
+            }
+
+            let mut data = [0u8; MAX_PACKET_LEN];
+            match self.read_packet(&mut data).await {
+                Ok(packet) => {
+                    queued = self.rx_queue.push(packet, &data);
+                }
+                Err(err) if self.rx_queue.is_empty() => return Err(err),
+                Err(_) => break,
+            }
         }
 
+        Ok(())
+    }
+
+    /// Read the packet at the head of the RX FIFO into `buffer`.
+    async fn read_packet(&mut self, buffer: &mut [u8]) -> Result<ReceivedPacket, RxError> {
+        // Usually the packet has long ended and can be read in bursts
+        let status0 = self
+            .ll
+            .status_0()
+            .read_async()
+            .await
+            .map_err(|_| RxError::Discarded)?;
         let mut token_buf = [0u8; 1];
         self.ll
             .fifo()
             .read_async(&mut token_buf)
             .await
-            .map_err(|_| DriverRxError::Discarded)?;
+            .map_err(|_| RxError::Discarded)?;
 
         // Check if the received byte is a valid Start-of-Packet token.
-        if (token_buf[0] & token::SOP_MASK) != token::SOP_PATTERN {
+        let Some(sop) = token::rx_sop_type(token_buf[0]) else {
             self.ll
                 .control_1()
                 .modify_async(|r| r.set_rx_flush(true))
                 .await
                 .ok();
-            return Err(DriverRxError::Discarded);
+            return Err(RxError::Discarded);
+        };
+
+        // A packet still arriving is read at the rate it comes in
+        let mut rx = RxProgress {
+            started: Instant::now(),
+            read: 1,
+            // SOP token, header and CRC, until the header tells more
+            end: 1 + 2 + 4,
+            complete: !status0.activity(),
+        };
+        let result = async {
+            let mut header_buf = [0u8; 2];
+            self.read_rx_fifo(&mut header_buf, &mut rx).await?;
+            let header = u16::from_le_bytes(header_buf);
+            let num_data_objects = ((header >> 12) & 0x7) as usize;
+            let extended = header & 0x8000 != 0;
+
+            let mut ext_header_buf = [0u8; 2];
+            let total_len = if extended {
+                self.read_rx_fifo(&mut ext_header_buf, &mut rx).await?;
+                let ext_header = u16::from_le_bytes(ext_header_buf);
+                let chunked = ext_header & 0x8000 != 0;
+                if chunked {
+                    2 + num_data_objects * 4
+                } else {
+                    // Unchunked: the whole Data Size follows the extended header
+                    4 + (ext_header & 0x1FF) as usize
+                }
+            } else {
+                2 + num_data_objects * 4
+            };
+            if total_len > buffer.len() || (extended && total_len < 4) {
+                return Err(RxError::Discarded);
+            }
+            rx.end = 1 + total_len + 4;
+
+            buffer[0..2].copy_from_slice(&header_buf);
+            let start = if extended {
+                buffer[2..4].copy_from_slice(&ext_header_buf);
+                4
+            } else {
+                2
+            };
+            self.read_rx_fifo(&mut buffer[start..total_len], &mut rx)
+                .await?;
+            let mut crc_buf = [0u8; 4];
+            self.read_rx_fifo(&mut crc_buf, &mut rx).await?;
+
+            Ok((total_len, u32::from_le_bytes(crc_buf)))
         }
+        .await;
 
-        let mut header_buf = [0u8; 2];
-        self.ll
-            .fifo()
-            .read_exact_async(&mut header_buf)
+        let (total_len, crc) = match result {
+            Ok(received) => received,
+            Err(err) => {
+                // Never hand out a truncated packet, or leave one behind
+                self.ll
+                    .control_1()
+                    .modify_async(|r| r.set_rx_flush(true))
+                    .await
+                    .ok();
+                return Err(err);
+            }
+        };
+
+        if !self.crc_valid(&buffer[..total_len], crc).await? {
+            self.crc_errors = self.crc_errors.wrapping_add(1);
+            return Err(RxError::BadCrc);
+        }
+
+        let header = u16::from_le_bytes([buffer[0], buffer[1]]);
+        let good_crc = header & 0xF01F == GOOD_CRC_MESSAGE_TYPE;
+        let mut acked_at = None;
+        if self.good_crc_mode == GoodCrcMode::Software && !good_crc {
+            // If this fails the partner simply retries
+            let message_id = ((header >> 9) & 0x7) as u8;
+            if self.transmit_good_crc(sop, message_id).await.is_ok() {
+                acked_at = Some(Instant::now());
+            }
+        }
+
+        Ok(ReceivedPacket {
+            sop,
+            len: total_len,
+            acked_at,
+        })
+    }
+
+    /// Check the CRC of a packet just read from the RX FIFO.
+    ///
+    /// `Status0.crc_chk` only describes the most recently received packet, so
+    /// it is used when nothing has arrived behind this one. Otherwise, and
+    /// always in [`GoodCrcMode::Software`] where the GoodCRC is waiting on
+    /// this, the CRC-32 is computed in software.
+    async fn crc_valid(&mut self, packet: &[u8], crc: u32) -> Result<bool, RxError> {
+        if self.good_crc_mode == GoodCrcMode::Software {
+            return Ok(crc::crc32(packet) == crc);
+        }
+
+        let status0 = self
+            .ll
+            .status_0()
+            .read_async()
             .await
-            .map_err(|_| DriverRxError::Discarded)?;
+            .map_err(|_| RxError::Discarded)?;
+        let status1 = self
+            .ll
+            .status_1()
+            .read_async()
+            .await
+            .map_err(|_| RxError::Discarded)?;
 
-        let header = u16::from_le_bytes(header_buf);
-        let num_data_objects = ((header >> 12) & 0x7) as usize;
-        let payload_len = num_data_objects * 4;
-        let total_len = 2 + payload_len;
+        if status1.rx_empty() {
+            Ok(status0.crc_chk())
+        } else {
+            Ok(crc::crc32(packet) == crc)
+        }
+    }
 
-        if total_len > buffer.len() {
-            self.ll
-                .control_1()
-                .modify_async(|r| r.set_rx_flush(true))
-                .await
-                .ok();
-            return Err(DriverRxError::Discarded);
+    /// Number of received packets dropped for a bad CRC since initialization.
+    pub fn crc_error_count(&self) -> u32 {
+        self.crc_errors
+    }
+}
+
+impl<I2CBus, E, V, I, O> SinkDriver for Fusb302b<I2CBus, E, V, I, O>
+where
+    I2CBus: I2c<Error = E> + 'static,
+    E: core::fmt::Debug,
+    V: VbusDetect,
+    I: InterruptPin,
+    O: OrientationSink,
+{
+    // Also true in GoodCrcMode::Software, where the driver sends GoodCRC
+    const HAS_AUTO_GOOD_CRC: bool = true;
+    const HAS_AUTO_RETRY: bool = true;
+
+    async fn wait_for_vbus(&mut self) {
+        // A source supplies VBUS itself
+        if let TypeCState::AttachedSrc(_) = self.typec.state {
+            return;
         }
 
-        buffer[0..2].copy_from_slice(&header_buf);
-        if payload_len > 0 {
-            self.ll
-                .fifo()
-                .read_exact_async(&mut buffer[2..total_len])
-                .await
-                .map_err(|_| DriverRxError::Discarded)?;
+        // Run the Type-C sink state machine up to Attached.SNK first
+        while self.wait_for_sink_attach().await.is_err() {
+            self.int_pin.wait_for_interrupt().await;
         }
 
-        let mut crc_buf = [0u8; 4];
+        match &mut self.vbus_source {
+            VbusSource::Internal => {
+                // Poll STATUS0.VBUSOK (R-only, bit 7) until VBUS is above ~4.0V.
+                loop {
+                    if let Ok(status0) = self.ll.status_0().read_async().await
+                        && status0.vbusok()
+                    {
+                        return;
+                    }
+                    self.int_pin.wait_for_interrupt().await;
+                }
+            }
+            VbusSource::External(v) => v.wait_for_vbus().await,
+        }
+    }
+
+    async fn transmit_hard_reset(&mut self) -> Result<(), DriverTxError> {
         self.ll
-            .fifo()
-            .read_exact_async(&mut crc_buf)
+            .control_3()
+            .modify_async(|r| r.set_send_hard_reset(true))
             .await
-            .map_err(|_| DriverRxError::Discarded)?;
+            .map_err(|_| DriverTxError::Discarded)?;
+
+        let deadline = Instant::now() + Duration::from_millis(5);
+        loop {
+            let irqa = self
+                .read_interrupta()
+                .await
+                .map_err(|_| DriverTxError::Discarded)?;
+            // The partner's Hard Reset crossed ours; either way both ports reset
+            if irqa.i_hardrst() {
+                return Ok(());
+            }
+            if irqa.i_hardsent() {
+                self.handle_hard_reset().await;
+                self.ll
+                    .interrupta()
+                    .modify_async(|r| r.set_i_hardsent(true))
+                    .await
+                    .ok();
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(DriverTxError::Discarded);
+            }
+            self.int_pin.wait_for_interrupt().await;
+        }
+    }
+
+    async fn transmit(&mut self, data: &[u8]) -> Result<(), DriverTxError> {
+        let handover = core::mem::replace(&mut self.soft_reset_handover, SoftResetHandover::Idle);
+        // The partner already accepted the automatic Soft Reset
+        if handover == SoftResetHandover::Reported && is_control_message(data, ACCEPT_MESSAGE_TYPE)
+        {
+            return Ok(());
+        }
+
+        let result = self.transmit_sop(SopType::Sop, data).await;
+        if result.is_err() && self.reset_event == Some(ResetEvent::SoftReset) {
+            // The packet is void after the reset; receive() reports the reset
+            self.reset_event = None;
+            self.soft_reset_handover = SoftResetHandover::Sent;
+            return Ok(());
+        }
+        result
+    }
+
+    async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, DriverRxError> {
+        let handover = core::mem::replace(&mut self.soft_reset_handover, SoftResetHandover::Idle);
+        let result = self.receive_sop(buffer).await;
+        // The partner's Accept to an automatic Soft Reset answers the driver;
+        // report the reset in its place
+        let soft_reset_sent = handover == SoftResetHandover::Sent
+            && match &result {
+                Ok(packet) => {
+                    packet.sop == SopType::Sop
+                        && is_control_message(&buffer[..packet.len], ACCEPT_MESSAGE_TYPE)
+                }
+                Err(RxError::Discarded) => true,
+                Err(_) => false,
+            };
+
+        // SOP' and SOP'' packets are only enabled for receive_sop() users
+        let packet = match result {
+            _ if soft_reset_sent && buffer.len() >= 2 => {
+                self.soft_reset_handover = SoftResetHandover::Reported;
+                let header = self.control_header(SopType::Sop, SOFT_RESET_MESSAGE_TYPE, 0, true);
+                buffer[..2].copy_from_slice(&header.to_le_bytes());
+                return Ok(2);
+            }
+            Err(RxError::SoftReset) if buffer.len() >= 2 => {
+                // The Soft Reset was flushed with the stale packets; hand the
+                // policy engine an equivalent one so it resets its MessageIDs
+                let header = self.control_header(SopType::Sop, SOFT_RESET_MESSAGE_TYPE, 0, true);
+                buffer[..2].copy_from_slice(&header.to_le_bytes());
+                return Ok(2);
+            }
+            result => result?,
+        };
+        if packet.sop != SopType::Sop {
+            return Err(DriverRxError::Discarded);
+        }
 
-        Ok(total_len)
+        Ok(packet.len)
     }
 }
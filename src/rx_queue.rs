@@ -0,0 +1,99 @@
+//! Packets drained from the RX FIFO ahead of `receive()`.
+
+use embassy_time::Instant;
+
+use crate::{MAX_PACKET_LEN, ReceivedPacket, RxError, SopType};
+
+/// Number of packets held back for later `receive()` calls.
+const RX_QUEUE_DEPTH: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    packet: ReceivedPacket,
+    data: [u8; MAX_PACKET_LEN],
+}
+
+impl Slot {
+    const EMPTY: Self = Self {
+        packet: ReceivedPacket {
+            sop: SopType::Sop,
+            len: 0,
+            acked_at: None,
+        },
+        data: [0; MAX_PACKET_LEN],
+    };
+}
+
+/// Ring buffer of received packets, oldest first.
+pub(crate) struct RxQueue {
+    slots: [Slot; RX_QUEUE_DEPTH],
+    head: usize,
+    count: usize,
+}
+
+impl RxQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            slots: [Slot::EMPTY; RX_QUEUE_DEPTH],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.count == RX_QUEUE_DEPTH
+    }
+
+    /// Drop all queued packets, e.g. after a Hard Reset or detach.
+    pub(crate) fn clear(&mut self) {
+        self.head = 0;
+        self.count = 0;
+    }
+
+    /// Queue `packet`, whose bytes are `data[..packet.len]`. Returns `false` if the queue is full.
+    pub(crate) fn push(&mut self, packet: ReceivedPacket, data: &[u8]) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        let slot = &mut self.slots[(self.head + self.count) % RX_QUEUE_DEPTH];
+        slot.packet = packet;
+        slot.data[..packet.len].copy_from_slice(&data[..packet.len]);
+        self.count += 1;
+        true
+    }
+
+    /// Record when the GoodCRC for the newest queued packet was sent.
+    pub(crate) fn set_newest_acked_at(&mut self, at: Instant) {
+        if self.is_empty() {
+            return;
+        }
+
+        let newest = (self.head + self.count - 1) % RX_QUEUE_DEPTH;
+        self.slots[newest].packet.acked_at = Some(at);
+    }
+
+    /// Move the oldest packet into `buffer`.
+    ///
+    /// A packet that does not fit `buffer` is dropped with [`RxError::Discarded`].
+    pub(crate) fn pop(&mut self, buffer: &mut [u8]) -> Option<Result<ReceivedPacket, RxError>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let slot = &self.slots[self.head];
+        self.head = (self.head + 1) % RX_QUEUE_DEPTH;
+        self.count -= 1;
+
+        let packet = slot.packet;
+        if packet.len > buffer.len() {
+            return Some(Err(RxError::Discarded));
+        }
+        buffer[..packet.len].copy_from_slice(&slot.data[..packet.len]);
+        Some(Ok(packet))
+    }
+}
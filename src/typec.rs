@@ -0,0 +1,1298 @@
+//! Type-C connection detection and state.
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_async::i2c::I2c;
+use usbpd_traits::{Driver as SinkDriver, DriverTxError};
+
+use crate::field_sets::{Mask, Maska, Maskb};
+use crate::{
+    BcLvl, CcPin, DataRolePort, Fusb302b, FusbError, GoodCrcMode, HostCurrent, InterruptPin,
+    OcpRange, OrientationSink, PowerRolePort, ToggleMode, ToggleState, VbusDetect, VbusSource,
+    bc_lvl_to_u8,
+};
+
+/// tCCDebounce: how long CC must be stable before a port is considered attached (100–200 ms).
+const T_CC_DEBOUNCE: Duration = Duration::from_millis(150);
+/// tPDDebounce: how long CC must be open before a port is considered detached (10–20 ms).
+const T_PD_DEBOUNCE: Duration = Duration::from_millis(15);
+/// tTryCCDebounce: CC debounce in the Try.SRC/Try.SNK states (10–20 ms).
+const T_TRY_CC_DEBOUNCE: Duration = Duration::from_millis(15);
+/// tDRPTry: how long a DRP tries its preferred role (75–150 ms).
+const T_DRP_TRY: Duration = Duration::from_millis(100);
+/// tRpValueChange: how long a new Rp value must be stable before a sink acts on it (10–20 ms).
+const T_RP_VALUE_CHANGE: Duration = Duration::from_millis(15);
+/// tSinkTx: how long a source waits after signalling SinkTxNG before starting an AMS (16–20 ms).
+const T_SINK_TX: Duration = Duration::from_millis(18);
+/// How long a sink waits for SinkTxOk before giving up on starting an AMS.
+const T_SINK_TX_OK_WAIT: Duration = Duration::from_millis(100);
+/// Time for BC_LVL to settle after switching the measured CC pin.
+const CC_MEASURE_SETTLE: Duration = Duration::from_millis(10);
+/// Sampling interval of the measured CC pin while debouncing.
+const CC_SAMPLE_INTERVAL: Duration = Duration::from_millis(2);
+/// tSafe0V + tSrcRecover + tSrcTurnOn: worst-case VBUS outage caused by a Hard Reset.
+const T_HARD_RESET_VBUS_RECOVERY: Duration = Duration::from_millis(1925);
+
+/// Type-C connection state, as defined by the USB Type-C specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TypeCState {
+    /// Unattached.SNK: Rd is presented on both CC pins, nothing is attached.
+    UnattachedSnk,
+    /// AttachWait.SNK: Rp was seen on the given CC pin and is being debounced.
+    AttachWaitSnk(CcPin),
+    /// Attached.SNK: attached to a source on the given CC pin.
+    AttachedSnk(CcPin),
+    /// Unattached.SRC: Rp is presented on both CC pins, nothing is attached.
+    UnattachedSrc,
+    /// AttachWait.SRC: Rd was seen on the given CC pin and is being debounced.
+    AttachWaitSrc(CcPin),
+    /// Attached.SRC: attached to a sink on the given CC pin.
+    AttachedSrc(CcPin),
+    /// Try.SRC: a DRP preferring the source role presents Rp to a partner on the given CC pin.
+    TrySrc(CcPin),
+    /// TryWait.SNK: Try.SRC failed, falling back to the sink role.
+    TryWaitSnk(CcPin),
+    /// Try.SNK: a DRP preferring the sink role presents Rd to a partner on the given CC pin.
+    TrySnk(CcPin),
+    /// TryWait.SRC: Try.SNK failed, falling back to the source role.
+    TryWaitSrc(CcPin),
+    /// AudioAccessory: Ra is seen on both CC pins while presenting Rp.
+    AudioAccessory,
+    /// UnorientedDebugAccessory.SRC: Rd is seen on both CC pins while presenting Rp.
+    DebugAccessorySrc,
+    /// DebugAccessory.SNK: Rp is seen on both CC pins while presenting Rd.
+    DebugAccessorySnk,
+}
+
+/// Role preference of a dual-role port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DrpPreference {
+    /// Accept whichever role the toggle engine settles on.
+    None,
+    /// Try.SNK: prefer the sink role when attached to another DRP.
+    TrySnk,
+    /// Try.SRC: prefer the source role when attached to another DRP.
+    TrySrc,
+}
+
+/// Termination presented by the port partner on a CC pin, as seen by a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum Termination {
+    /// Nothing attached.
+    Open,
+    /// Ra: a powered cable or an audio accessory.
+    Ra,
+    /// Rd: a sink.
+    Rd,
+}
+
+/// Current a source allows on VBUS, as advertised by its Rp value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TypeCCurrent {
+    /// Default USB current (500 mA for USB 2.0, 900 mA for USB 3.x).
+    Default,
+    /// 1.5 A at 5 V.
+    Current1A5,
+    /// 3.0 A at 5 V.
+    Current3A0,
+}
+
+/// MDAC code for the 2.05 V threshold separating Rp 3.0 A from open (Table 5).
+const MDAC_VOPEN_3A0: u8 = 0b11_0100;
+
+/// MDAC codes for the vRd (attach/detach) and vRa thresholds at a given Rp current (Table 6).
+///
+/// At default current Ra is detected with BC_LVL instead of the MDAC.
+fn mdac_thresholds(host_cur: HostCurrent) -> (u8, Option<u8>) {
+    match host_cur {
+        // 2.6 V and 0.8 V
+        HostCurrent::High330UA => (0b11_1110, Some(0b01_0011)),
+        // 1.6 V and 0.42 V
+        HostCurrent::Medium180UA => (0b10_0110, Some(0b00_1010)),
+        // 1.6 V and BC_LVL
+        HostCurrent::Default80UA | HostCurrent::NoCurrent => (0b10_0110, None),
+    }
+}
+
+/// Type-C connection bookkeeping kept by the driver.
+pub(crate) struct TypeC {
+    pub(crate) state: TypeCState,
+    /// When the active CC pin was first seen open, for detach debouncing.
+    cc_open_since: Option<Instant>,
+    /// VBUS loss is expected (Hard Reset) and not a detach until this instant.
+    vbus_holdoff_until: Option<Instant>,
+    /// VCONN was switched off by a fault the user has not taken yet.
+    vconn_fault: Option<VconnFault>,
+}
+
+impl TypeC {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: TypeCState::UnattachedSnk,
+            cc_open_since: None,
+            vbus_holdoff_until: None,
+            vconn_fault: None,
+        }
+    }
+
+    /// Forget the connection, keeping a VCONN fault latched until it is taken.
+    fn unattach(&mut self) {
+        *self = Self {
+            vconn_fault: self.vconn_fault,
+            ..Self::new()
+        };
+    }
+
+    /// A Hard Reset was sent or received: the source will cycle VBUS.
+    pub(crate) fn hard_reset(&mut self) {
+        self.vbus_holdoff_until = Some(Instant::now() + T_HARD_RESET_VBUS_RECOVERY);
+    }
+}
+
+/// Reason VCONN was switched off by the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VconnFault {
+    /// The VCONN switch hit its over-current limit (`Status1.ocp`).
+    OverCurrent,
+    /// The die temperature is too high (`Status1.ovrtemp`).
+    OverTemperature,
+}
+
+/// Attach result reported by the autonomous toggle engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Attachment {
+    /// A source was found; the port is attached as a sink on the given CC pin.
+    Sink(CcPin),
+    /// A sink was found; the port is attached as a source on the given CC pin.
+    Source(CcPin),
+    /// An audio adapter accessory (Ra on both CC pins) was found.
+    AudioAccessory,
+    /// A debug accessory presenting Rd on both CC pins was found; the port is its source.
+    DebugAccessorySrc,
+    /// A debug accessory presenting Rp on both CC pins was found; the port is its sink.
+    DebugAccessorySnk,
+}
+
+impl<I2CBus, E, V, I, O> Fusb302b<I2CBus, E, V, I, O>
+where
+    I2CBus: I2c<Error = E> + 'static,
+    E: core::fmt::Debug,
+    O: OrientationSink,
+{
+    /// Current Type-C connection state.
+    pub fn type_c_state(&self) -> TypeCState {
+        self.typec.state
+    }
+
+    /// Enter Unattached.SNK: present Rd on both CC pins and disconnect the transmitter.
+    pub(crate) async fn enter_unattached_snk(&mut self) -> Result<(), FusbError<E>> {
+        self.ll
+            .switches_1()
+            .modify_async(|r| {
+                r.set_txcc_1(false);
+                r.set_txcc_2(false);
+            })
+            .await?;
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(true);
+                r.set_pdwn_2(true);
+                r.set_meas_cc_1(true);
+                r.set_meas_cc_2(false);
+            })
+            .await?;
+        self.clear_orientation().await;
+        self.typec.unattach();
+
+        Ok(())
+    }
+
+    /// Enable or disable audio and debug accessory detection in [`Self::wait_for_attach`].
+    ///
+    /// Sets `Control4.tog_exit_aud` together with `Control2.tog_rd_only`, so
+    /// the toggle engine stops on Ra on both CC pins but no longer on a single
+    /// Ra (e.g. an unplugged powered cable). Once the toggle engine settles,
+    /// both CC pins are measured to tell Rd/Rd and Rp/Rp debug accessories
+    /// apart from a regular sink or source.
+    pub async fn set_accessory_detection(&mut self, enabled: bool) -> Result<(), FusbError<E>> {
+        self.ll
+            .control_2()
+            .modify_async(|r| r.set_tog_rd_only(enabled))
+            .await?;
+        self.ll
+            .control_4()
+            .write_async(|r| r.set_tog_exit_aud(enabled))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Check a toggle engine result for an accessory by measuring both CC pins.
+    ///
+    /// An accessory has to be seen for tCCDebounce, as in AttachWait.SNK and
+    /// AttachWait.SRC, and a change restarts the debounce. Anything else is
+    /// returned as soon as it is seen, for the sink or source path to
+    /// debounce, and `None` when nothing is attached after all. Accessories
+    /// leave CC1 as the measured pin, with the comparator at the vRd
+    /// threshold as a source, so [`Self::poll_detach`] can see them go.
+    async fn detect_accessory(
+        &mut self,
+        attachment: Attachment,
+    ) -> Result<Option<Attachment>, FusbError<E>> {
+        let mut detected = self.classify_accessory(attachment).await?;
+        let mut since = Instant::now();
+        loop {
+            let state = match detected {
+                Some(Attachment::AudioAccessory) => TypeCState::AudioAccessory,
+                Some(Attachment::DebugAccessorySrc) => TypeCState::DebugAccessorySrc,
+                Some(Attachment::DebugAccessorySnk) => TypeCState::DebugAccessorySnk,
+                Some(Attachment::Sink(_) | Attachment::Source(_)) | None => return Ok(detected),
+            };
+            if Instant::now() - since >= T_CC_DEBOUNCE {
+                self.typec.state = state;
+                return Ok(detected);
+            }
+
+            Timer::after(CC_SAMPLE_INTERVAL).await;
+            let now = self.classify_accessory(attachment).await?;
+            if now != detected {
+                detected = now;
+                since = Instant::now();
+            }
+        }
+    }
+
+    /// Classify a toggle engine result from one measurement of both CC pins.
+    async fn classify_accessory(
+        &mut self,
+        attachment: Attachment,
+    ) -> Result<Option<Attachment>, FusbError<E>> {
+        Ok(Some(match attachment {
+            Attachment::Sink(_) => {
+                let cc2 = self.measure_cc(CcPin::Cc2).await?;
+                let cc1 = self.measure_cc(CcPin::Cc1).await?;
+                if matches!(cc1, BcLvl::LessThan200MV) || matches!(cc2, BcLvl::LessThan200MV) {
+                    attachment
+                } else {
+                    Attachment::DebugAccessorySnk
+                }
+            }
+            Attachment::Source(_) | Attachment::AudioAccessory => {
+                let cc2 = self.measure_termination(CcPin::Cc2).await?;
+                let cc1 = self.measure_termination(CcPin::Cc1).await?;
+                match (cc1, cc2) {
+                    (Termination::Rd, Termination::Rd) => Attachment::DebugAccessorySrc,
+                    (Termination::Ra, Termination::Ra) => Attachment::AudioAccessory,
+                    // A sink, with or without a powered cable on the other pin
+                    (Termination::Rd, _) => Attachment::Source(CcPin::Cc1),
+                    (_, Termination::Rd) => Attachment::Source(CcPin::Cc2),
+                    _ => return Ok(None),
+                }
+            }
+            Attachment::DebugAccessorySrc | Attachment::DebugAccessorySnk => attachment,
+        }))
+    }
+
+    /// Tell the orientation sink the plug is gone, if an orientation was reported.
+    async fn clear_orientation(&mut self) {
+        if let TypeCState::AttachedSnk(_) | TypeCState::AttachedSrc(_) = self.typec.state {
+            self.orientation.set_orientation(None).await;
+        }
+    }
+
+    /// Restore the GoodCRC roles of the Type-C attach state after a Hard Reset.
+    ///
+    /// A Hard Reset undoes any PR_Swap or DR_Swap: a sink goes back to UFP
+    /// and a source to DFP.
+    pub(crate) async fn restore_default_roles(&mut self) -> Result<(), FusbError<E>> {
+        let (power_role, data_role) = match self.typec.state {
+            TypeCState::AttachedSnk(_) | TypeCState::DebugAccessorySnk => {
+                (PowerRolePort::Sink, DataRolePort::Ufp)
+            }
+            TypeCState::AttachedSrc(_)
+            | TypeCState::AudioAccessory
+            | TypeCState::DebugAccessorySrc => (PowerRolePort::Source, DataRolePort::Dfp),
+            _ => return Ok(()),
+        };
+
+        self.set_roles(power_role, data_role).await
+    }
+
+    /// Check an attached port for detach, and clean up if the partner went away.
+    ///
+    /// A sink detaches when VBUS falls below VBUSOK. With an external
+    /// [`VbusDetect`], or while VBUS is expected to be cycled by a Hard Reset,
+    /// the active CC pin reading vRa for tPDDebounce is used instead. A source
+    /// detaches when the active CC pin rises above the vRd threshold for
+    /// tPDDebounce; the caller is responsible for removing VBUS afterwards.
+    ///
+    /// On detach the PD logic is reset, both FIFOs are flushed and the port
+    /// returns to its unattached state, so the next attach re-runs orientation
+    /// detection. Returns `true` if the port was detached.
+    pub async fn poll_detach(&mut self) -> Result<bool, FusbError<E>> {
+        let source = match self.typec.state {
+            TypeCState::AttachedSnk(_) | TypeCState::DebugAccessorySnk => false,
+            TypeCState::AttachedSrc(_)
+            | TypeCState::AudioAccessory
+            | TypeCState::DebugAccessorySrc => true,
+            _ => return Ok(false),
+        };
+
+        let status0 = self.ll.status_0().read_async().await?;
+        let now = Instant::now();
+        let vbus_holdoff = self
+            .typec
+            .vbus_holdoff_until
+            .is_some_and(|until| now < until);
+
+        let detached = match self.vbus_source {
+            VbusSource::Internal if !source && !vbus_holdoff => !status0.vbusok(),
+            _ => {
+                // Sink: Rp gone (vRa). Source: Rd gone (COMP above the vRd threshold).
+                let open = if source {
+                    status0.comp()
+                } else {
+                    matches!(status0.bc_lvl(), BcLvl::LessThan200MV)
+                };
+                if open {
+                    now - *self.typec.cc_open_since.get_or_insert(now) >= T_PD_DEBOUNCE
+                } else {
+                    self.typec.cc_open_since = None;
+                    false
+                }
+            }
+        };
+
+        if detached {
+            self.reset_pd_logic().await?;
+            self.exit_bist_test_data().await?;
+            if source {
+                self.enter_unattached_src().await?;
+            } else {
+                self.enter_unattached_snk().await?;
+            }
+        }
+
+        Ok(detached)
+    }
+
+    /// Read the current advertised by the source's Rp on the active CC pin (datasheet Table 5).
+    ///
+    /// BC_LVL tells Default and 1.5 A apart; above 1.23 V the comparator is
+    /// checked at 2.05 V to distinguish Rp 3.0 A from an open pin. Returns
+    /// `None` when not in Attached.SNK or when no Rp is seen.
+    pub async fn type_c_current(&mut self) -> Result<Option<TypeCCurrent>, FusbError<E>> {
+        let TypeCState::AttachedSnk(_) = self.typec.state else {
+            return Ok(None);
+        };
+
+        let status0 = self.ll.status_0().read_async().await?;
+        Ok(match status0.bc_lvl() {
+            BcLvl::LessThan200MV => None,
+            BcLvl::Between200And660MV => Some(TypeCCurrent::Default),
+            BcLvl::Between660And1230MV => Some(TypeCCurrent::Current1A5),
+            BcLvl::GreaterThan1230MV => {
+                if self.compare_cc(MDAC_VOPEN_3A0).await? {
+                    None
+                } else {
+                    Some(TypeCCurrent::Current3A0)
+                }
+            }
+        })
+    }
+
+    /// End an Atomic Message Sequence started with [`Self::transmit_ams_start`].
+    ///
+    /// As a source this switches Rp back to SinkTxOk (3.0 A) so the sink may
+    /// start its own AMS again. Does nothing as a sink.
+    pub async fn end_ams(&mut self) -> Result<(), FusbError<E>> {
+        if let TypeCState::AttachedSrc(_) = self.typec.state {
+            self.set_source_rp(HostCurrent::High330UA).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Change the advertised Rp current in Attached.SRC, moving the detach
+    /// threshold along with it.
+    async fn set_source_rp(&mut self, current: HostCurrent) -> Result<(), FusbError<E>> {
+        let (rd_mdac, _) = mdac_thresholds(current);
+        self.ll
+            .control_0()
+            .modify_async(|r| r.set_host_cur(current))
+            .await?;
+        self.ll
+            .measure()
+            .write_async(|r| {
+                r.set_meas_vbus(false);
+                r.set_mdac(rd_mdac);
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Set the VCONN over-current limit to `(cur + 1) / 8` of `range` (datasheet Table 29).
+    ///
+    /// `cur` is the 3-bit `Ocpreg.ocp_cur` value, so anything above 7 fails
+    /// with [`FusbError::OutOfRange`]; the reset default is the full 800 mA
+    /// range.
+    pub async fn configure_vconn_ocp(
+        &mut self,
+        range: OcpRange,
+        cur: u8,
+    ) -> Result<(), FusbError<E>> {
+        if cur > 0b111 {
+            return Err(FusbError::OutOfRange);
+        }
+        self.ll
+            .ocpreg()
+            .write_async(|r| {
+                r.set_ocp_range(range);
+                r.set_ocp_cur(cur);
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Source VCONN on the CC pin opposite the active one.
+    ///
+    /// Only possible in Attached.SNK or Attached.SRC, otherwise this fails
+    /// with [`FusbError::NotAttached`]. Rd, Rp and the measurement are taken
+    /// off the VCONN pin first. Any latched fault is cleared. The switch
+    /// turns itself off on over-current, and the driver also clears
+    /// `Switches0.vconn_cc*` on `I_OCP_TEMP`; see [`Self::take_vconn_fault`].
+    pub async fn enable_vconn(&mut self) -> Result<(), FusbError<E>> {
+        let (TypeCState::AttachedSnk(cc) | TypeCState::AttachedSrc(cc)) = self.typec.state else {
+            return Err(FusbError::NotAttached);
+        };
+        let cc1 = cc == CcPin::Cc1;
+        self.typec.vconn_fault = None;
+        self.ll
+            .switches_0()
+            .modify_async(|r| {
+                if cc1 {
+                    r.set_pdwn_2(false);
+                    r.set_pu_en_2(false);
+                    r.set_meas_cc_2(false);
+                } else {
+                    r.set_pdwn_1(false);
+                    r.set_pu_en_1(false);
+                    r.set_meas_cc_1(false);
+                }
+                r.set_vconn_cc_1(!cc1);
+                r.set_vconn_cc_2(cc1);
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stop sourcing VCONN.
+    ///
+    /// As a sink, Rd goes back onto the pin that sourced VCONN.
+    pub async fn disable_vconn(&mut self) -> Result<(), FusbError<E>> {
+        let sink = matches!(self.typec.state, TypeCState::AttachedSnk(_));
+        self.ll
+            .switches_0()
+            .modify_async(|r| {
+                if sink && r.vconn_cc_1() {
+                    r.set_pdwn_1(true);
+                }
+                if sink && r.vconn_cc_2() {
+                    r.set_pdwn_2(true);
+                }
+                r.set_vconn_cc_1(false);
+                r.set_vconn_cc_2(false);
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Check `Status1` for an over-current or over-temperature condition and
+    /// switch VCONN off if one is present.
+    ///
+    /// Called by the driver when it sees `I_OCP_TEMP`; call it directly when
+    /// not running [`SinkDriver::receive`]. Returns the fault, which stays
+    /// latched for [`Self::take_vconn_fault`].
+    pub async fn poll_vconn_fault(&mut self) -> Result<Option<VconnFault>, FusbError<E>> {
+        let status1 = self.ll.status_1().read_async().await?;
+        let fault = if status1.ocp() {
+            Some(VconnFault::OverCurrent)
+        } else if status1.ovrtemp() {
+            Some(VconnFault::OverTemperature)
+        } else {
+            None
+        };
+
+        if fault.is_some() {
+            self.disable_vconn().await?;
+            self.typec.vconn_fault = fault;
+        }
+
+        Ok(fault)
+    }
+
+    /// Take the fault that last switched VCONN off, if any.
+    pub fn take_vconn_fault(&mut self) -> Option<VconnFault> {
+        self.typec.vconn_fault.take()
+    }
+
+    /// Reset the PD logic and flush both FIFOs at a connection boundary.
+    async fn reset_pd_logic(&mut self) -> Result<(), FusbError<E>> {
+        self.ll
+            .reset()
+            .write_async(|r| r.set_pd_reset(true))
+            .await?;
+        self.ll
+            .control_0()
+            .modify_async(|r| r.set_tx_flush(true))
+            .await?;
+        self.ll
+            .control_1()
+            .modify_async(|r| r.set_rx_flush(true))
+            .await?;
+        self.rx_queue.clear();
+
+        Ok(())
+    }
+
+    /// Enter Unattached.SRC: present Rp on both CC pins and disconnect the transmitter.
+    ///
+    /// The Rp current is whatever `Control0.host_cur` is currently set to.
+    async fn enter_unattached_src(&mut self) -> Result<(), FusbError<E>> {
+        self.ll
+            .switches_1()
+            .modify_async(|r| {
+                r.set_txcc_1(false);
+                r.set_txcc_2(false);
+            })
+            .await?;
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(false);
+                r.set_pdwn_2(false);
+                r.set_pu_en_1(true);
+                r.set_pu_en_2(true);
+                r.set_meas_cc_1(true);
+                r.set_meas_cc_2(false);
+            })
+            .await?;
+        self.clear_orientation().await;
+        self.typec.unattach();
+        self.typec.state = TypeCState::UnattachedSrc;
+
+        Ok(())
+    }
+
+    /// Determine the termination on `cc` while presenting Rp (datasheet Table 6).
+    ///
+    /// The MDAC thresholds follow the current `Control0.host_cur` setting. On
+    /// return the comparator is left at the vRd threshold, so `Status0.comp`
+    /// reads `true` while the pin is open.
+    async fn measure_termination(&mut self, cc: CcPin) -> Result<Termination, FusbError<E>> {
+        let cc1 = cc == CcPin::Cc1;
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(false);
+                r.set_pdwn_2(false);
+                r.set_pu_en_1(true);
+                r.set_pu_en_2(true);
+                r.set_meas_cc_1(cc1);
+                r.set_meas_cc_2(!cc1);
+            })
+            .await?;
+
+        let host_cur = self.ll.control_0().read_async().await?.host_cur();
+        let (rd_mdac, ra_mdac) = mdac_thresholds(host_cur);
+
+        // Anything below the vRa threshold is Ra
+        let ra = match ra_mdac {
+            Some(mdac) => !self.compare_cc(mdac).await?,
+            None => {
+                Timer::after(CC_MEASURE_SETTLE).await;
+                let status0 = self.ll.status_0().read_async().await?;
+                matches!(status0.bc_lvl(), BcLvl::LessThan200MV)
+            }
+        };
+        // Anything above the vRd threshold is open
+        let open = self.compare_cc(rd_mdac).await?;
+
+        Ok(if open {
+            Termination::Open
+        } else if ra {
+            Termination::Ra
+        } else {
+            Termination::Rd
+        })
+    }
+
+    /// Set the MDAC to `mdac` and return `Status0.comp` (CC above the threshold).
+    async fn compare_cc(&mut self, mdac: u8) -> Result<bool, FusbError<E>> {
+        self.ll
+            .measure()
+            .write_async(|r| {
+                r.set_meas_vbus(false);
+                r.set_mdac(mdac);
+            })
+            .await?;
+
+        Timer::after(CC_MEASURE_SETTLE).await;
+
+        Ok(self.ll.status_0().read_async().await?.comp())
+    }
+
+    /// Route the BC_LVL comparators to `cc` (keeping Rd on both pins) and read them.
+    async fn measure_cc(&mut self, cc: CcPin) -> Result<BcLvl, FusbError<E>> {
+        let cc1 = cc == CcPin::Cc1;
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(true);
+                r.set_pdwn_2(true);
+                r.set_meas_cc_1(cc1);
+                r.set_meas_cc_2(!cc1);
+            })
+            .await?;
+
+        Timer::after(CC_MEASURE_SETTLE).await;
+
+        Ok(self.ll.status_0().read_async().await?.bc_lvl())
+    }
+
+    /// Present Rd on both CC pins and route the BMC transceiver to `cc`.
+    async fn configure_sink_cc(&mut self, cc: CcPin) -> Result<(), FusbError<E>> {
+        let cc1 = cc == CcPin::Cc1;
+        let auto_crc = self.good_crc_mode == GoodCrcMode::Hardware;
+
+        // TX on the selected CC line, AUTO_CRC per the GoodCRC mode, SPECREV kept
+        self.ll
+            .switches_1()
+            .modify_async(|r| {
+                r.set_txcc_1(cc1);
+                r.set_txcc_2(!cc1);
+                r.set_auto_crc(auto_crc);
+                r.set_powerrole(PowerRolePort::Sink);
+                r.set_datarole(DataRolePort::Ufp);
+            })
+            .await?;
+        self.cache_roles(PowerRolePort::Sink, DataRolePort::Ufp);
+        // Measure the selected CC line
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(true);
+                r.set_pdwn_2(true);
+                r.set_meas_cc_1(cc1);
+                r.set_meas_cc_2(!cc1);
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Present Rp on `cc` and route the BMC transceiver to it.
+    ///
+    /// The comparator is set to the vRd threshold so a detach shows up as `Status0.comp`.
+    async fn configure_source_cc(&mut self, cc: CcPin) -> Result<(), FusbError<E>> {
+        let cc1 = cc == CcPin::Cc1;
+        let auto_crc = self.good_crc_mode == GoodCrcMode::Hardware;
+
+        let host_cur = self.ll.control_0().read_async().await?.host_cur();
+        let (rd_mdac, _) = mdac_thresholds(host_cur);
+        self.ll
+            .measure()
+            .write_async(|r| {
+                r.set_meas_vbus(false);
+                r.set_mdac(rd_mdac);
+            })
+            .await?;
+
+        self.ll
+            .switches_1()
+            .modify_async(|r| {
+                r.set_txcc_1(cc1);
+                r.set_txcc_2(!cc1);
+                r.set_auto_crc(auto_crc);
+                r.set_powerrole(PowerRolePort::Source);
+                r.set_datarole(DataRolePort::Dfp);
+            })
+            .await?;
+        self.cache_roles(PowerRolePort::Source, DataRolePort::Dfp);
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(false);
+                r.set_pdwn_2(false);
+                r.set_pu_en_1(cc1);
+                r.set_pu_en_2(!cc1);
+                r.set_meas_cc_1(cc1);
+                r.set_meas_cc_2(!cc1);
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl<I2CBus, E, V, I, O> Fusb302b<I2CBus, E, V, I, O>
+where
+    I2CBus: I2c<Error = E> + 'static,
+    E: core::fmt::Debug,
+    V: VbusDetect,
+    I: InterruptPin,
+    O: OrientationSink,
+{
+    /// Let the FUSB302B poll the CC pins autonomously until something attaches.
+    ///
+    /// `mode` selects whether the chip presents as a sink (`SnkPolling`), a
+    /// source (`SrcPolling`) or toggles between both (`DrpPolling`). The MCU
+    /// only wakes up on `I_TOGDONE`, so this can be awaited indefinitely with
+    /// nothing plugged in. Once the toggle state settles, the CC switches are
+    /// configured for the detected role and orientation. Accessories are only
+    /// reported after [`Self::set_accessory_detection`]; a toggle result the
+    /// CC pins do not confirm restarts the toggle engine.
+    pub async fn wait_for_attach(&mut self, mode: ToggleMode) -> Result<Attachment, FusbError<E>> {
+        loop {
+            if let Some(attachment) = self.toggle_until_attach(mode).await? {
+                return Ok(attachment);
+            }
+        }
+    }
+
+    /// One run of the toggle engine for [`Self::wait_for_attach`], or `None`
+    /// if the result was gone by the time it was debounced.
+    async fn toggle_until_attach(
+        &mut self,
+        mode: ToggleMode,
+    ) -> Result<Option<Attachment>, FusbError<E>> {
+        // Hand the CC switches over to the toggle state machine
+        self.ll
+            .control_2()
+            .modify_async(|r| r.set_toggle(false))
+            .await?;
+        self.ll
+            .switches_0()
+            .write_async(|r| {
+                r.set_pdwn_1(false);
+                r.set_pdwn_2(false);
+            })
+            .await?;
+        self.ll
+            .switches_1()
+            .modify_async(|r| {
+                r.set_txcc_1(false);
+                r.set_txcc_2(false);
+            })
+            .await?;
+        self.ll
+            .control_0()
+            .modify_async(|r| r.set_host_cur(HostCurrent::Default80UA))
+            .await?;
+        self.ll
+            .measure()
+            .modify_async(|r| r.set_meas_vbus(false))
+            .await?;
+
+        // Datasheet Table 4: bandgap, receiver and measure block only
+        self.ll
+            .power()
+            .write_async(|r| {
+                r.set_pwr_0_bandgap_and_wake_enable(true);
+                r.set_pwr_1_receiver_and_measure_refs_enable(true);
+                r.set_pwr_2_measure_block_power_enable(true);
+                r.set_pwr_3_internal_oscillator_enable(false);
+            })
+            .await?;
+
+        // Only I_TOGDONE and I_BC_LVL may assert INT_N while toggling
+        // (Mask = 0xFE, Maska = 0xBF, Maskb = 0x01)
+        self.ll
+            .mask()
+            .write_async(|r| {
+                r.set_m_vbusok(true);
+                r.set_m_activity(true);
+                r.set_m_comp_chng(true);
+                r.set_m_crc_chk(true);
+                r.set_m_alert(true);
+                r.set_m_wake(true);
+                r.set_m_collision(true);
+                r.set_m_bc_lvl(false);
+            })
+            .await?;
+        self.ll
+            .maska()
+            .write_async(|r| {
+                r.set_m_ocp_temp(true);
+                r.set_m_togdone(false);
+                r.set_m_softfail(true);
+                r.set_m_retryfail(true);
+                r.set_m_hardsent(true);
+                r.set_m_txsent(true);
+                r.set_m_softrst(true);
+                r.set_m_hardrst(true);
+            })
+            .await?;
+        self.ll
+            .maskb()
+            .write_async(|r| r.set_m_gcrcsent(true))
+            .await?;
+
+        // Clear stale interrupts before starting the toggle engine
+        self.ll.interrupt().read_async().await?;
+        self.ll.interrupta().read_async().await?;
+        self.ll.interruptb().read_async().await?;
+
+        self.ll
+            .control_2()
+            .modify_async(|r| {
+                r.set_mode(mode);
+                r.set_toggle(true);
+            })
+            .await?;
+
+        let attachment = loop {
+            // Clear I_BC_LVL too, or INT_N stays asserted and this busy-polls
+            self.ll.interrupt().read_async().await?;
+            if self.ll.interrupta().read_async().await?.i_togdone() {
+                match self.ll.status_1_a().read_async().await?.togss() {
+                    Ok(ToggleState::SnkCc1) => break Attachment::Sink(CcPin::Cc1),
+                    Ok(ToggleState::SnkCc2) => break Attachment::Sink(CcPin::Cc2),
+                    Ok(ToggleState::SrcCc1) => break Attachment::Source(CcPin::Cc1),
+                    Ok(ToggleState::SrcCc2) => break Attachment::Source(CcPin::Cc2),
+                    Ok(ToggleState::AudioAccessory) => break Attachment::AudioAccessory,
+                    Ok(ToggleState::Running) | Err(_) => {}
+                }
+            }
+            self.int_pin.wait_for_interrupt().await;
+        };
+
+        // Take manual control of the CC switches again
+        self.ll
+            .control_2()
+            .modify_async(|r| r.set_toggle(false))
+            .await?;
+        self.ll
+            .power()
+            .write_async(|r| {
+                r.set_pwr_0_bandgap_and_wake_enable(true);
+                r.set_pwr_1_receiver_and_measure_refs_enable(true);
+                r.set_pwr_2_measure_block_power_enable(true);
+                r.set_pwr_3_internal_oscillator_enable(true);
+            })
+            .await?;
+        self.ll.mask().write_async(|r| *r = Mask::new()).await?;
+        self.ll.maska().write_async(|r| *r = Maska::new()).await?;
+        self.ll.maskb().write_async(|r| *r = Maskb::new()).await?;
+
+        // An audio accessory result is debounced even without accessory detection
+        let accessories = self.ll.control_4().read_async().await?.tog_exit_aud();
+        let attachment = if accessories || attachment == Attachment::AudioAccessory {
+            match self.detect_accessory(attachment).await? {
+                Some(attachment) => attachment,
+                None => return Ok(None),
+            }
+        } else {
+            attachment
+        };
+
+        match attachment {
+            Attachment::Sink(cc) => {
+                // Debounced by wait_for_sink_attach()
+                self.configure_sink_cc(cc).await?;
+                self.typec.state = TypeCState::AttachWaitSnk(cc);
+            }
+            Attachment::Source(cc) => {
+                // Debounced by wait_for_source_attach()
+                self.configure_source_cc(cc).await?;
+                self.typec.state = TypeCState::AttachWaitSrc(cc);
+            }
+            Attachment::AudioAccessory => {
+                // Keep presenting Rp on both pins, as the toggle engine did
+                self.measure_termination(CcPin::Cc1).await?;
+                self.typec.state = TypeCState::AudioAccessory;
+            }
+            // CC pins already set up by detect_accessory()
+            Attachment::DebugAccessorySrc | Attachment::DebugAccessorySnk => {}
+        }
+
+        Ok(Some(attachment))
+    }
+
+    /// Run the Type-C sink state machine until the port reaches Attached.SNK.
+    ///
+    /// Rp must be seen on a CC pin for tCCDebounce and VBUS must be
+    /// present before the port is considered attached; CC glitches shorter
+    /// than that restart the debounce, and an open CC for tPDDebounce returns
+    /// to Unattached.SNK. With an external [`VbusDetect`] the VBUS check is
+    /// a wait on the user detector after the CC debounce completes.
+    pub async fn wait_for_sink_attach(&mut self) -> Result<CcPin, FusbError<E>> {
+        loop {
+            match self.typec.state {
+                TypeCState::UnattachedSnk => {
+                    let cc1 = bc_lvl_to_u8(self.measure_cc(CcPin::Cc1).await?);
+                    let cc2 = bc_lvl_to_u8(self.measure_cc(CcPin::Cc2).await?);
+                    if cc1 > 0 || cc2 > 0 {
+                        // Select the CC line with higher voltage (indicates connection)
+                        let cc = if cc1 > cc2 { CcPin::Cc1 } else { CcPin::Cc2 };
+                        self.typec.state = TypeCState::AttachWaitSnk(cc);
+                    }
+                }
+                TypeCState::AttachWaitSnk(cc) => {
+                    if !self.attach_sink(cc, T_CC_DEBOUNCE).await? {
+                        self.enter_unattached_snk().await?;
+                    }
+                }
+                TypeCState::AttachedSnk(cc) => return Ok(cc),
+                _ => self.enter_unattached_snk().await?,
+            }
+        }
+    }
+
+    /// Wait in Attached.SNK until the source goes away, then return to Unattached.SNK.
+    ///
+    /// See [`Self::poll_detach`] for the detach condition.
+    pub async fn wait_for_sink_detach(&mut self) -> Result<(), FusbError<E>> {
+        let TypeCState::AttachedSnk(_) = self.typec.state else {
+            return Ok(());
+        };
+
+        while !self.poll_detach().await? {
+            match self.vbus_source {
+                VbusSource::Internal => self.int_pin.wait_for_interrupt().await,
+                VbusSource::External(_) => Timer::after(CC_SAMPLE_INTERVAL).await,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Wait in Attached.SNK until the advertised Rp current changes.
+    ///
+    /// Woken by `I_BC_LVL`; a new value is only reported once it has been
+    /// stable for tRpValueChange. Returns the new value, or `None` once the
+    /// source detaches (see [`Self::poll_detach`]) or if the port is not in
+    /// Attached.SNK.
+    pub async fn wait_for_type_c_current_change(
+        &mut self,
+    ) -> Result<Option<TypeCCurrent>, FusbError<E>> {
+        let initial = self.type_c_current().await?;
+        if initial.is_none() {
+            return Ok(None);
+        }
+
+        loop {
+            if self.ll.interrupt().read_async().await?.i_bc_lvl() {
+                Timer::after(T_RP_VALUE_CHANGE).await;
+                let current = self.type_c_current().await?;
+                // No Rp at all is left to the detach check below
+                if current.is_some() && current != initial {
+                    return Ok(current);
+                }
+            }
+            if self.poll_detach().await? {
+                return Ok(None);
+            }
+            self.int_pin.wait_for_interrupt().await;
+        }
+    }
+
+    /// Transmit the first message of an Atomic Message Sequence with PD 3.0
+    /// collision avoidance.
+    ///
+    /// As a sink the message is held back while the source advertises
+    /// SinkTxNG (Rp 1.5 A) and sent once Rp is back at SinkTxOk (3.0 A). If a
+    /// packet from the source arrives meanwhile, or SinkTxOk does not come
+    /// back within 100 ms, this fails with [`DriverTxError::Discarded`] so
+    /// the policy engine can serve the source's AMS first. As a
+    /// source Rp is switched to SinkTxNG and the message is sent after
+    /// tSinkTx; call [`Self::end_ams`] once the sequence is complete. Only use
+    /// this once PD 3.0 has been negotiated, as a PD 2.0 source may never
+    /// advertise SinkTxOk. Messages within an AMS go through
+    /// [`SinkDriver::transmit`] as usual.
+    pub async fn transmit_ams_start(&mut self, data: &[u8]) -> Result<(), DriverTxError> {
+        match self.typec.state {
+            TypeCState::AttachedSnk(_) => {
+                let deadline = Instant::now() + T_SINK_TX_OK_WAIT;
+                loop {
+                    // SinkTxOk is Rp 3.0 A; an open CC is left to poll_detach()
+                    let status0 = self
+                        .ll
+                        .status_0()
+                        .read_async()
+                        .await
+                        .map_err(|_| DriverTxError::Discarded)?;
+                    if matches!(status0.bc_lvl(), BcLvl::GreaterThan1230MV) {
+                        break;
+                    }
+
+                    let irqa = self
+                        .read_interrupta()
+                        .await
+                        .map_err(|_| DriverTxError::Discarded)?;
+                    if irqa.i_hardrst() {
+                        return Err(DriverTxError::HardReset);
+                    }
+                    if self
+                        .poll_detach()
+                        .await
+                        .map_err(|_| DriverTxError::Discarded)?
+                    {
+                        return Err(DriverTxError::HardReset);
+                    }
+
+                    // The source has started an AMS of its own
+                    let status1 = self
+                        .ll
+                        .status_1()
+                        .read_async()
+                        .await
+                        .map_err(|_| DriverTxError::Discarded)?;
+                    if !status1.rx_empty() || !self.rx_queue.is_empty() {
+                        return Err(DriverTxError::Discarded);
+                    }
+                    if Instant::now() >= deadline {
+                        return Err(DriverTxError::Discarded);
+                    }
+                    Timer::after(CC_SAMPLE_INTERVAL).await;
+                }
+            }
+            TypeCState::AttachedSrc(_) => {
+                self.set_source_rp(HostCurrent::Medium180UA)
+                    .await
+                    .map_err(|_| DriverTxError::Discarded)?;
+                Timer::after(T_SINK_TX).await;
+            }
+            _ => {}
+        }
+
+        self.transmit(data).await
+    }
+
+    /// Run the Type-C source state machine until the port reaches Attached.SRC.
+    ///
+    /// Rp is presented on both CC pins at `current`. A sink is attached once
+    /// Rd is seen on exactly one CC pin for tCCDebounce, with the other pin
+    /// open or Ra; an open CC for tPDDebounce returns to Unattached.SRC. On
+    /// attach `Switches1.powerrole` is set to Source and the caller should
+    /// turn on VBUS.
+    pub async fn wait_for_source_attach(
+        &mut self,
+        current: HostCurrent,
+    ) -> Result<CcPin, FusbError<E>> {
+        self.ll
+            .control_0()
+            .modify_async(|r| r.set_host_cur(current))
+            .await?;
+
+        loop {
+            match self.typec.state {
+                TypeCState::UnattachedSrc => {
+                    let cc1 = self.measure_termination(CcPin::Cc1).await?;
+                    let cc2 = self.measure_termination(CcPin::Cc2).await?;
+                    match (cc1, cc2) {
+                        (Termination::Rd, Termination::Open | Termination::Ra) => {
+                            self.typec.state = TypeCState::AttachWaitSrc(CcPin::Cc1);
+                        }
+                        (Termination::Open | Termination::Ra, Termination::Rd) => {
+                            self.typec.state = TypeCState::AttachWaitSrc(CcPin::Cc2);
+                        }
+                        _ => {}
+                    }
+                }
+                TypeCState::AttachWaitSrc(cc) => {
+                    if !self.attach_source(cc, T_CC_DEBOUNCE).await? {
+                        self.enter_unattached_src().await?;
+                    }
+                }
+                TypeCState::AttachedSrc(cc) => return Ok(cc),
+                _ => self.enter_unattached_src().await?,
+            }
+        }
+    }
+
+    /// Run a dual-role port: toggle between SNK and SRC until a partner attaches.
+    ///
+    /// The toggle engine resolves the initial role. With
+    /// [`DrpPreference::TrySrc`] a port that settled as a sink first tries
+    /// presenting Rp for tDRPTry (Try.SRC) before falling back to TryWait.SNK,
+    /// and with [`DrpPreference::TrySnk`] a port that settled as a source
+    /// tries Rd first (Try.SNK) before falling back to TryWait.SRC. As a
+    /// source, Rp is advertised at `current`. On attach `Switches1.powerrole`
+    /// and `datarole` are programmed for the resolved role. Failed attach
+    /// attempts go back to toggling.
+    pub async fn wait_for_drp_attach(
+        &mut self,
+        preference: DrpPreference,
+        current: HostCurrent,
+    ) -> Result<Attachment, FusbError<E>> {
+        loop {
+            let attachment = self.wait_for_attach(ToggleMode::DrpPolling).await?;
+            self.ll
+                .control_0()
+                .modify_async(|r| r.set_host_cur(current))
+                .await?;
+
+            let attached = match (attachment, preference) {
+                (Attachment::Sink(cc), DrpPreference::TrySrc) => self.try_src(cc).await?,
+                (Attachment::Sink(cc), _) => self
+                    .attach_sink(cc, T_CC_DEBOUNCE)
+                    .await?
+                    .then_some(Attachment::Sink(cc)),
+                (Attachment::Source(cc), DrpPreference::TrySnk) => self.try_snk(cc).await?,
+                (Attachment::Source(cc), _) => self
+                    .attach_source(cc, T_CC_DEBOUNCE)
+                    .await?
+                    .then_some(Attachment::Source(cc)),
+                (accessory, _) => Some(accessory),
+            };
+
+            if let Some(attachment) = attached {
+                return Ok(attachment);
+            }
+        }
+    }
+
+    /// Try.SRC, then TryWait.SNK, after the toggle engine settled as a sink on `cc`.
+    async fn try_src(&mut self, cc: CcPin) -> Result<Option<Attachment>, FusbError<E>> {
+        self.typec.state = TypeCState::TrySrc(cc);
+        let deadline = Instant::now() + T_DRP_TRY;
+        while Instant::now() < deadline {
+            if self.measure_termination(cc).await? == Termination::Rd
+                && self.attach_source(cc, T_TRY_CC_DEBOUNCE).await?
+            {
+                return Ok(Some(Attachment::Source(cc)));
+            }
+        }
+
+        self.typec.state = TypeCState::TryWaitSnk(cc);
+        Ok(self
+            .attach_sink(cc, T_CC_DEBOUNCE)
+            .await?
+            .then_some(Attachment::Sink(cc)))
+    }
+
+    /// Try.SNK, then TryWait.SRC, after the toggle engine settled as a source on `cc`.
+    async fn try_snk(&mut self, cc: CcPin) -> Result<Option<Attachment>, FusbError<E>> {
+        self.typec.state = TypeCState::TrySnk(cc);
+        self.measure_cc(cc).await?;
+        Timer::after(T_DRP_TRY).await;
+        if self.attach_sink(cc, T_TRY_CC_DEBOUNCE).await? {
+            return Ok(Some(Attachment::Sink(cc)));
+        }
+
+        self.typec.state = TypeCState::TryWaitSrc(cc);
+        Ok(self
+            .attach_source(cc, T_TRY_CC_DEBOUNCE)
+            .await?
+            .then_some(Attachment::Source(cc)))
+    }
+
+    /// Debounce Rp on `cc` for `stable` and enter Attached.SNK. Returns `false` on detach.
+    async fn attach_sink(&mut self, cc: CcPin, stable: Duration) -> Result<bool, FusbError<E>> {
+        if !self.debounce_sink_attach(cc, stable).await? {
+            return Ok(false);
+        }
+        self.configure_sink_cc(cc).await?;
+        self.reset_pd_logic().await?;
+        self.typec.state = TypeCState::AttachedSnk(cc);
+        self.orientation.set_orientation(Some(cc)).await;
+
+        Ok(true)
+    }
+
+    /// Debounce Rd on `cc` for `stable` and enter Attached.SRC. Returns `false` on detach.
+    async fn attach_source(&mut self, cc: CcPin, stable: Duration) -> Result<bool, FusbError<E>> {
+        if !self.debounce_source_attach(cc, stable).await? {
+            return Ok(false);
+        }
+        self.configure_source_cc(cc).await?;
+        self.reset_pd_logic().await?;
+        self.typec.state = TypeCState::AttachedSrc(cc);
+        self.orientation.set_orientation(Some(cc)).await;
+
+        Ok(true)
+    }
+
+    /// Debounce Rd on `cc` for `stable`. Returns `true` once attached, `false` on detach.
+    async fn debounce_source_attach(
+        &mut self,
+        cc: CcPin,
+        stable: Duration,
+    ) -> Result<bool, FusbError<E>> {
+        // Leaves the comparator at the vRd threshold
+        self.measure_termination(cc).await?;
+
+        let mut rd_since = Instant::now();
+        let mut open_since = None;
+        loop {
+            let status0 = self.ll.status_0().read_async().await?;
+            let now = Instant::now();
+            if status0.comp() {
+                if now - *open_since.get_or_insert(now) >= T_PD_DEBOUNCE {
+                    return Ok(false);
+                }
+            } else {
+                // A glitch to open restarts the debounce
+                if open_since.take().is_some() {
+                    rd_since = now;
+                }
+                if now - rd_since >= stable {
+                    return Ok(true);
+                }
+            }
+            Timer::after(CC_SAMPLE_INTERVAL).await;
+        }
+    }
+
+    /// Debounce Rp on `cc` for `stable`, then wait for VBUS. Returns `true`
+    /// once attached, `false` on detach.
+    async fn debounce_sink_attach(
+        &mut self,
+        cc: CcPin,
+        stable: Duration,
+    ) -> Result<bool, FusbError<E>> {
+        self.measure_cc(cc).await?;
+
+        let mut rp_since = Instant::now();
+        let mut open_since = None;
+        loop {
+            let status0 = self.ll.status_0().read_async().await?;
+            let now = Instant::now();
+            if matches!(status0.bc_lvl(), BcLvl::LessThan200MV) {
+                if now - *open_since.get_or_insert(now) >= T_PD_DEBOUNCE {
+                    return Ok(false);
+                }
+            } else {
+                // A glitch to open restarts the debounce
+                if open_since.take().is_some() {
+                    rp_since = now;
+                }
+                if now - rp_since >= stable {
+                    match &mut self.vbus_source {
+                        VbusSource::Internal => {
+                            if status0.vbusok() {
+                                return Ok(true);
+                            }
+                        }
+                        VbusSource::External(v) => {
+                            v.wait_for_vbus().await;
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+            Timer::after(CC_SAMPLE_INTERVAL).await;
+        }
+    }
+}